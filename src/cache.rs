@@ -0,0 +1,461 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::prelude::{RwLock, TypeMapKey};
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler};
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, File, Input};
+use songbird::tracks::PlayMode;
+use symphonia::core::io::MediaSource;
+use tokio::process::Command;
+
+use crate::commands::{get_track_info, TrackInfo, TrackSource};
+use crate::filters::{Filter, FilteredInput, FilterSource};
+use crate::playlists::SavedTrack;
+use crate::storage;
+use crate::youtube;
+
+const INDEX_FILE: &str = "index.ron";
+
+/// A downloaded track, along with its metadata so that resolving it needs no yt-dlp run either.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+    pub file: PathBuf,
+    pub size: u64,
+    /// Unix timestamp of the last time it was served, for eviction.
+    pub last_used: u64,
+    pub track: SavedTrack,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct AudioCache {
+    entries: HashMap<String, CacheEntry>,
+    #[serde(skip)]
+    dir: PathBuf,
+    #[serde(skip)]
+    budget: u64,
+    #[serde(skip)]
+    downloading: HashSet<String>,
+    #[serde(skip)]
+    pub hits: u64,
+    #[serde(skip)]
+    pub misses: u64,
+}
+
+pub(crate) struct AudioCacheKey;
+
+impl TypeMapKey for AudioCacheKey {
+    type Value = Arc<RwLock<AudioCache>>;
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// The URL a source is cached under, with YouTube links reduced to the video so that playlist and
+/// timestamp parameters do not split the cache. Searches are keyed by the video they resolved to.
+pub(crate) fn cache_key(info: &TrackInfo) -> Option<String> {
+    let url = match info.source.as_ref()? {
+        TrackSource::Url(url) => url.as_str(),
+        // Spotify tracks are searches too, but their metadata links to Spotify rather than to the
+        // video the search found, which yt-dlp cannot download. Those are not cached.
+        TrackSource::Search(_) => info.metadata.source_url.as_deref()
+            .filter(|url| youtube::video_id(url).is_some())?,
+        TrackSource::File(_) | TrackSource::Http(_) => return None,
+    };
+
+    canonical_url(url)
+}
+
+pub(crate) fn canonical_url(url: &str) -> Option<String> {
+    if !url.starts_with("http") {
+        return None;
+    }
+
+    match youtube::video_id(url) {
+        Some(id) => Some(format!("https://www.youtube.com/watch?v={}", id)),
+        None => Some(url.split('#').next().unwrap_or(url).to_string()),
+    }
+}
+
+impl AudioCache {
+    /// Reads the cache index from `dir`, dropping entries whose files are gone.
+    pub fn load(dir: &str, budget: u64) -> AudioCache {
+        let dir = PathBuf::from(dir);
+        let mut cache: AudioCache = storage::load(&dir.join(INDEX_FILE), "the audio cache index")
+            .unwrap_or_default();
+        cache.entries.retain(|_, entry| entry.file.is_file());
+        cache.dir = dir;
+        cache.budget = budget;
+        cache.remove_orphans();
+
+        cache
+    }
+
+    /// Deletes downloads the index does not know about, left behind by downloads that were cut short
+    /// or finished after the last time the index was saved. Only files named like the cache names its
+    /// downloads are touched, in case the directory is shared with something else.
+    fn remove_orphans(&self) {
+        if !self.enabled() {
+            return;
+        }
+        let files = match std::fs::read_dir(&self.dir) {
+            Ok(files) => files,
+            Err(_) => return,
+        };
+
+        // yt-dlp may spell the directory differently than the configuration, so names are compared.
+        let known: HashSet<&OsStr> = self.entries.values().filter_map(|entry| entry.file.file_name()).collect();
+        for path in files.flatten().map(|file| file.path()) {
+            let name = match path.file_name() {
+                Some(name) => name,
+                None => continue,
+            };
+            if !path.is_file() || !is_download_name(name) || known.contains(name) {
+                continue;
+            }
+            if let Err(error) = std::fs::remove_file(&path) {
+                println!("Failed to delete stray cache file {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        let serialized = storage::serialize(self, 4);
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        storage::write_async(self.dir.join(INDEX_FILE), serialized).await
+    }
+
+    /// A budget of zero turns the cache off.
+    pub fn enabled(&self) -> bool {
+        self.budget > 0
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.entries.values().map(|entry| entry.size).sum()
+    }
+
+    /// Entries by most recently used first.
+    pub fn entries(&self) -> Vec<(String, CacheEntry)> {
+        let mut entries: Vec<_> = self.entries.iter()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        entries.sort_by_key(|(_, entry)| Reverse(entry.last_used));
+
+        entries
+    }
+
+    /// Looks a key up without marking it as used.
+    pub fn peek(&self, key: &str) -> Option<CacheEntry> {
+        self.entries.get(key).cloned()
+    }
+
+    /// Looks a key up, counting the hit or miss and marking the entry as used.
+    pub fn get(&mut self, key: &str) -> Option<CacheEntry> {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = now();
+                self.hits += 1;
+                Some(entry.clone())
+            },
+            None => {
+                self.misses += 1;
+                None
+            },
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<CacheEntry> {
+        let entry = self.entries.remove(key)?;
+        if let Err(error) = std::fs::remove_file(&entry.file) {
+            println!("Failed to delete cached {}: {}", entry.file.display(), error);
+        }
+
+        Some(entry)
+    }
+
+    /// Deletes every entry, returning how many bytes were freed.
+    pub fn purge(&mut self) -> u64 {
+        let keys: Vec<String> = self.entries.keys().cloned().collect();
+        keys.iter()
+            .filter_map(|key| self.remove(key))
+            .map(|entry| entry.size)
+            .sum()
+    }
+
+    fn insert(&mut self, key: String, entry: CacheEntry) {
+        if let Some(previous) = self.entries.insert(key, entry) {
+            let _ = std::fs::remove_file(previous.file);
+        }
+
+        // Least recently used entries go first until everything fits the budget again.
+        while self.size() > self.budget {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => {
+                    self.remove(&key);
+                },
+                None => break,
+            }
+        }
+    }
+}
+
+/// The name `download` files are saved under, hashed from their key.
+fn download_name(key: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Whether `name` looks like a download, `<hash>.<ext>`, or one of yt-dlp's temporary files for it.
+fn is_download_name(name: &OsStr) -> bool {
+    let name = match name.to_str() {
+        Some(name) => name,
+        None => return false,
+    };
+
+    match name.split_once('.') {
+        Some((stem, extension)) => stem.len() == 16 && stem.chars().all(|c| c.is_ascii_hexdigit()) && !extension.is_empty(),
+        None => false,
+    }
+}
+
+/// Downloads the best audio of `url` into `dir`, returning the path yt-dlp wrote.
+async fn download(url: &str, dir: &Path, name: &str) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    let output = Command::new("yt-dlp")
+        .args(["-f", "bestaudio/best", "--no-playlist", "--quiet", "--no-simulate", "--print", "after_move:filepath"])
+        .arg("-o")
+        .arg(dir.join(format!("{}.%(ext)s", name)))
+        .arg(url)
+        .output()
+        .await?;
+
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || path.is_empty() {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(std::io::Error::other(reason));
+    }
+
+    Ok(PathBuf::from(path))
+}
+
+/// Swaps in the cached download of a track when its input is created, right before it plays. Entries
+/// evicted or purged while the track waited in the queue fall back to `source`.
+pub(crate) struct CachedInput {
+    pub cache: Arc<RwLock<AudioCache>>,
+    pub key: String,
+    pub filter: Filter,
+    pub start: Duration,
+    pub source: Option<Box<dyn Compose>>,
+}
+
+/// Creates the stream of `compose` the way the driver would, with blocking ones off the runtime.
+async fn create_stream(mut compose: Box<dyn Compose>) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+    if compose.should_create_async() {
+        return compose.create_async().await;
+    }
+
+    tokio::task::spawn_blocking(move || compose.create())
+        .await
+        .map_err(|error| AudioStreamError::Fail(Box::new(error)))?
+}
+
+#[async_trait]
+impl Compose for CachedInput {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let entry = self.cache.write().await.get(&self.key);
+        let compose: Box<dyn Compose> = match entry {
+            // Offsets and filters are left to ffmpeg, like for any other source.
+            Some(entry) if self.filter != Filter::Off || !self.start.is_zero() => Box::new(FilteredInput {
+                source: FilterSource::Direct(entry.file.to_string_lossy().into_owned()),
+                filter: self.filter,
+                start: self.start,
+            }),
+            Some(entry) => Box::new(File::new(entry.file)),
+            None => self.source.take().ok_or(AudioStreamError::Unsupported)?,
+        };
+
+        create_stream(compose).await
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        match &mut self.source {
+            Some(source) => source.aux_metadata().await,
+            None => Err(AudioStreamError::Unsupported),
+        }
+    }
+}
+
+impl From<CachedInput> for Input {
+    fn from(input: CachedInput) -> Self {
+        Input::Lazy(Box::new(input))
+    }
+}
+
+/// Caches tracks that streamed to the end, so the next play of the same URL comes from disk.
+///
+/// The played stream is not kept, so the track is downloaded again once it ends. That doubles the
+/// bandwidth of a track's first play, in exchange for never caching tracks that were skipped.
+pub(crate) struct AudioCacher {
+    pub cache: Arc<RwLock<AudioCache>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for AudioCacher {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(track_list) = ctx {
+            for (state, handle) in *track_list {
+                // Skipped tracks may not have been streamed completely. `PlayMode` compares skipped
+                // and finished tracks as equal, so the variant is matched instead.
+                if !matches!(state.playing, PlayMode::End) {
+                    continue;
+                }
+
+                let info = get_track_info(handle).await;
+                // Live streams have no duration and would never finish downloading.
+                let (key, source) = match (cache_key(&info), info.source.clone(), info.metadata.duration) {
+                    (Some(key), Some(source), Some(_)) => (key, source),
+                    _ => continue,
+                };
+
+                let dir = {
+                    let mut cache = self.cache.write().await;
+                    if !cache.enabled() || cache.entries.contains_key(&key) || !cache.downloading.insert(key.clone()) {
+                        continue;
+                    }
+                    cache.dir.clone()
+                };
+
+                let cache = self.cache.clone();
+                let track = SavedTrack::new(info.metadata, source);
+                tokio::spawn(async move {
+                    let downloaded = download(&key, &dir, &download_name(&key)).await;
+
+                    let mut cache = cache.write().await;
+                    cache.downloading.remove(&key);
+                    let file = match downloaded {
+                        Ok(file) => file,
+                        Err(error) => {
+                            println!("Failed to cache {}: {}", key, error);
+                            return;
+                        },
+                    };
+
+                    let size = std::fs::metadata(&file).map_or(0, |metadata| metadata.len());
+                    cache.insert(key, CacheEntry { file, size, last_used: now(), track });
+                    if let Err(error) = cache.save().await {
+                        println!("Failed to save the audio cache index: {}", error);
+                    }
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(size: u64, last_used: u64) -> CacheEntry {
+        CacheEntry {
+            file: PathBuf::from(format!("missing-{}", last_used)),
+            size,
+            last_used,
+            track: SavedTrack {
+                title: None,
+                artist: None,
+                url: None,
+                thumbnail: None,
+                duration: None,
+                source: TrackSource::Url(String::new()),
+            },
+        }
+    }
+
+    fn cache(budget: u64) -> AudioCache {
+        AudioCache { budget, ..AudioCache::default() }
+    }
+
+    #[test]
+    fn canonical_url_collapses_youtube_parameters() {
+        let expected = Some(String::from("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert_eq!(canonical_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"), expected);
+        assert_eq!(canonical_url("https://youtube.com/watch?v=dQw4w9WgXcQ&t=90s"), expected);
+        assert_eq!(canonical_url("https://www.youtube.com/watch?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG&v=dQw4w9WgXcQ&index=3"), expected);
+        assert_eq!(canonical_url("https://youtu.be/dQw4w9WgXcQ?t=42"), expected);
+        assert_eq!(canonical_url("https://music.youtube.com/watch?v=dQw4w9WgXcQ"), expected);
+    }
+
+    #[test]
+    fn canonical_url_keeps_other_links() {
+        assert_eq!(canonical_url("https://soundcloud.com/artist/track#t=1:00"),
+            Some(String::from("https://soundcloud.com/artist/track")));
+        assert_eq!(canonical_url("never gonna give you up"), None);
+    }
+
+    #[test]
+    fn insert_evicts_least_recently_used_first() {
+        let mut cache = cache(100);
+        cache.insert(String::from("a"), entry(40, 1));
+        cache.insert(String::from("b"), entry(40, 3));
+        cache.insert(String::from("c"), entry(10, 2));
+        assert_eq!(cache.size(), 90);
+
+        cache.insert(String::from("d"), entry(30, 4));
+        assert!(cache.peek("a").is_none());
+        assert!(cache.peek("b").is_some() && cache.peek("c").is_some() && cache.peek("d").is_some());
+        assert_eq!(cache.size(), 80);
+    }
+
+    #[test]
+    fn insert_drops_an_entry_larger_than_the_budget() {
+        let mut cache = cache(100);
+        cache.insert(String::from("a"), entry(40, 1));
+        cache.insert(String::from("huge"), entry(150, 2));
+
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn only_download_names_are_cleaned_up() {
+        assert!(is_download_name(OsStr::new("0123456789abcdef.webm")));
+        assert!(is_download_name(OsStr::new("0123456789abcdef.webm.part")));
+        assert!(!is_download_name(OsStr::new(INDEX_FILE)));
+        assert!(!is_download_name(OsStr::new("song.mp3")));
+        assert!(!is_download_name(OsStr::new("0123456789abcdef")));
+        assert!(!is_download_name(OsStr::new("0123456789abcdeg.webm")));
+    }
+}
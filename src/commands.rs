@@ -1,6 +1,11 @@
 use std::alloc::handle_alloc_error;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use reqwest::Client;
 use serenity::{async_trait, client::Context, framework::standard::{
@@ -8,19 +13,40 @@ use serenity::{async_trait, client::Context, framework::standard::{
     macros::{check, command, group, help},
 }, model::channel::Message, prelude::*};
 use serenity::all::Builder;
+use serenity::builder::{CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+                        CreateInteractionResponseMessage, CreateMessage, CreateSelectMenu, CreateSelectMenuKind,
+                        CreateSelectMenuOption, EditMessage};
+use serenity::futures::StreamExt;
+use serenity::model::application::{ButtonStyle, ComponentInteraction, ComponentInteractionDataKind};
 use serenity::framework::standard::{Args, CommandGroup, CommandOptions, help_commands, HelpOptions, Reason};
-use serenity::http::CacheHttp;
+use serenity::http::{CacheHttp, Http};
 use serenity::model::gateway::Ready;
-use serenity::model::id::UserId;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
 use serenity::model::Permissions;
-use songbird::{EventContext, TrackEvent};
+use songbird::{Call, EventContext, Songbird, TrackEvent};
+use songbird::tracks::{PlayError, PlayMode, Track, TrackHandle, TrackQueue};
 use songbird::events::{Event, EventHandler as VoiceEventHandler};
-use songbird::input::{Compose, YoutubeDl};
+use songbird::input::{AudioStreamError, AuxMetadata, Compose, File, HttpRequest, Input, YoutubeDl};
 use songbird::typemap::IntoBox;
+use serde::{Deserialize, Serialize};
 //use youtube_dl::YoutubeDl;
-use tokio::signal::windows::ctrl_break;
 
+use rand::seq::SliceRandom;
+
+use crate::cache::{AudioCache, AudioCacheKey, AudioCacher, CachedInput, cache_key, canonical_url};
+use crate::config::ConfigKey;
+use crate::filters::{Filter, FilteredInput, FilterSource};
+use crate::history::{HistoryKey, HistoryRecorder};
 use crate::hooks::CommandCounter;
+use crate::library::{self, LibraryEntry};
+use crate::playlists::{Playlists, PlaylistsKey, SavedTrack, Scope};
+use crate::settings::{GuildSettings, SettingsKey};
+use crate::soundboard::{self, ResumeAfterClip};
+use crate::snapshots::{QueueSnapshot, QueueSnapshotter, schedule_snapshot, Snapshots, SnapshotsKey};
+use crate::state::{GuildStateKey, GuildStates, LoopMode};
+use crate::spotify::{self, SpotifyKey, SpotifyLink};
+use crate::utils;
+use crate::youtube;
 
 pub(crate) struct HttpKey;
 
@@ -28,6 +54,91 @@ impl TypeMapKey for HttpKey {
     type Value = Client;
 }
 
+const QUEUE_PAGE_SIZE: usize = 10;
+const LIBRARY_PAGE_SIZE: usize = 20;
+const SEARCH_RESULTS: usize = 5;
+const HISTORY_PAGE_SIZE: usize = 10;
+const RESUME_OFFER_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// Percentage points the controller's volume buttons change the volume by.
+const VOLUME_STEP: u16 = 10;
+
+/// Where a track came from, so that a fresh input can be created for it again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum TrackSource {
+    Url(String),
+    Search(String),
+    File(PathBuf),
+    Http(String),
+}
+
+impl TrackSource {
+    pub fn to_input(&self, http_client: Client) -> Input {
+        match self {
+            TrackSource::Url(url) => YoutubeDl::new(http_client, url.clone()).into(),
+            TrackSource::Search(query) => YoutubeDl::new_search(http_client, query.clone()).into(),
+            TrackSource::File(path) => File::new(path.clone()).into(),
+            TrackSource::Http(url) => HttpRequest::new(http_client, url.clone()).into(),
+        }
+    }
+
+    /// Plays the source through ffmpeg with `filter`, starting `start` into it.
+    pub fn to_filtered_input(&self, filter: Filter, start: Duration) -> Input {
+        let source = match self {
+            TrackSource::Url(url) => FilterSource::YtDlp(url.clone()),
+            TrackSource::Search(query) => FilterSource::YtDlp(format!("ytsearch1:{}", query)),
+            TrackSource::File(path) => FilterSource::Direct(path.to_string_lossy().into_owned()),
+            TrackSource::Http(url) => FilterSource::Direct(url.clone()),
+        };
+
+        FilteredInput { source, filter, start }.into()
+    }
+}
+
+/// Context attached to every enqueued track through its typemap.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TrackInfo {
+    pub metadata: AuxMetadata,
+    pub source: Option<TrackSource>,
+    pub requester: Option<UserId>,
+    pub channel_id: Option<ChannelId>,
+    /// Where into the source playback began, when the input was created mid-track.
+    pub start: Duration,
+    pub filter: Filter,
+    /// Set once the handle was swapped for a rebuilt one, so that its end is not counted as a play.
+    pub replaced: bool,
+}
+
+impl TrackInfo {
+    pub fn new(metadata: AuxMetadata, source: TrackSource, msg: &Message) -> Self {
+        TrackInfo {
+            metadata,
+            source: Some(source),
+            requester: Some(msg.author.id),
+            channel_id: Some(msg.channel_id),
+            start: Duration::ZERO,
+            filter: Filter::Off,
+            replaced: false,
+        }
+    }
+
+    /// Swaps `input` for a filtered one when the guild has a filter set and the source can be recreated.
+    pub fn apply_filter(&mut self, input: Input, filter: Filter) -> Input {
+        match (&self.source, filter) {
+            (Some(source), filter) if filter != Filter::Off => {
+                self.filter = filter;
+                source.to_filtered_input(filter, self.start)
+            },
+            _ => input,
+        }
+    }
+}
+
+pub(crate) struct TrackInfoKey;
+
+impl TypeMapKey for TrackInfoKey {
+    type Value = TrackInfo;
+}
+
 pub(crate) struct Handler;
 #[async_trait]
 impl EventHandler for Handler {
@@ -36,7 +147,12 @@ impl EventHandler for Handler {
     }
 }
 
-pub(crate) struct TrackErrorNotifier;
+/// Reports failed tracks to the channel they were requested from and drops them from the queue.
+pub(crate) struct TrackErrorNotifier {
+    http: Arc<Http>,
+    queue: TrackQueue,
+}
+
 #[async_trait]
 impl VoiceEventHandler for TrackErrorNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
@@ -47,16 +163,268 @@ impl VoiceEventHandler for TrackErrorNotifier {
                     handle.uuid(),
                     state.playing
                 );
+
+                // The head of the queue advances on its own once the track ends, but a track
+                // that failed while preloading would otherwise stall the queue when its turn comes.
+                let uuid = handle.uuid();
+                self.queue.modify_queue(|queue| {
+                    if let Some(index) = queue.iter().skip(1).position(|queued| queued.uuid() == uuid) {
+                        queue.remove(index + 1);
+                    }
+                });
+
+                let info = get_track_info(handle).await;
+                let channel_id = match info.channel_id {
+                    Some(channel_id) => channel_id,
+                    None => continue,
+                };
+
+                let reason = match &state.playing {
+                    PlayMode::Errored(error) => describe_play_error(error),
+                    _ => String::from("unknown error"),
+                };
+                let requester = info.requester
+                    .map_or(String::new(), |user| format!(" (requested by <@{}>)", user));
+
+                let _ = channel_id.say(&self.http, format!(
+                    ":warning: Could not play **{}**{}: {}. Skipping it.",
+                    track_title(&info.metadata), requester, reason
+                )).await;
+            }
+        }
+        None
+    }
+}
+
+/// Applies the guild's loop mode whenever a track starts or finishes.
+pub(crate) struct TrackLooper {
+    manager: Arc<Songbird>,
+    guild_id: GuildId,
+    states: GuildStates,
+    http_client: Client,
+    cache: Arc<RwLock<AudioCache>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackLooper {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(track_list) = ctx {
+            let (loop_mode, filter) = self.states.read().await
+                .get(&self.guild_id)
+                .map(|state| (state.loop_mode, state.filter))
+                .unwrap_or_default();
+
+            for (state, handle) in *track_list {
+                if !is_queued_track(handle).await {
+                    continue;
+                }
+
+                match (loop_mode, &state.playing) {
+                    (LoopMode::Track, PlayMode::Play) => {
+                        let _ = handle.enable_loop();
+                    },
+                    // Skipped and stopped tracks end in `PlayMode::Stop` and are not brought back.
+                    (LoopMode::Queue, PlayMode::End) => {
+                        let mut info = get_track_info(handle).await;
+                        let source = match &info.source {
+                            Some(source) => source.to_input(self.http_client.clone()),
+                            None => continue,
+                        };
+                        info.start = Duration::ZERO;
+                        info.filter = Filter::Off;
+                        let source = info.apply_filter(source, filter);
+
+                        if let Some(handler_lock) = self.manager.get(self.guild_id) {
+                            let mut handler = handler_lock.lock().await;
+                            enqueue_track(&mut handler, &self.cache, source, info, state.volume).await;
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Keeps the music going with a related track once the last queued one ends, where the guild enabled it.
+struct Autoplayer {
+    ctx: Context,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for Autoplayer {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(track_list) = ctx {
+            for (state, handle) in *track_list {
+                // Skipped and stopped tracks end in `PlayMode::Stop`, which should stay quiet. A last
+                // track that failed would stop the music just the same as one that finished.
+                let ended = matches!(state.playing, PlayMode::End | PlayMode::Errored(_));
+                if ended && is_queued_track(handle).await {
+                    queue_related(&self.ctx, self.guild_id, handle).await;
+                }
             }
         }
         None
     }
 }
 
+async fn queue_related(ctx: &Context, guild_id: GuildId, ended: &TrackHandle) {
+    let settings = guild_settings(ctx, guild_id).await;
+    if !settings.autoplay || guild_loop_mode(ctx, guild_id).await != LoopMode::Off {
+        return;
+    }
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+    let handler_lock = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock,
+        None => return,
+    };
+
+    let (channel, queued) = {
+        let handler = handler_lock.lock().await;
+        (handler.current_channel(), handler.queue().current_queue())
+    };
+
+    // The queue may or may not have dropped the ended track yet.
+    if queued.iter().any(|track| track.uuid() != ended.uuid()) {
+        return;
+    }
+    // Nobody is left to listen, the idle timer takes it from here.
+    if channel.is_none_or(|channel| listeners(ctx, guild_id, channel).is_empty()) {
+        return;
+    }
+
+    let seed = get_track_info(ended).await;
+    let (text_channel, filter) = guild_states(ctx).await
+        .read().await
+        .get(&guild_id)
+        .map(|state| (state.text_channel, state.filter))
+        .unwrap_or_default();
+
+    let (metadata, source) = match related_track(ctx, guild_id, &seed).await {
+        Some(related) => related,
+        None => {
+            if let Some(channel_id) = text_channel {
+                let _ = channel_id.say(&ctx.http, "Autoplay found nothing new to play, stopping here.").await;
+            }
+            return;
+        },
+    };
+
+    let title = track_title(&metadata);
+    let mut info = TrackInfo {
+        metadata,
+        source: Some(source.clone()),
+        requester: None,
+        channel_id: text_channel,
+        start: Duration::ZERO,
+        filter: Filter::Off,
+        replaced: false,
+    };
+    let input = info.apply_filter(source.to_input(get_http_client(ctx).await), filter);
+    let cache = audio_cache(ctx).await;
+    enqueue_track(&mut *handler_lock.lock().await, &cache, input, info, f32::from(settings.volume) / 100.0).await;
+
+    if let Some(channel_id) = text_channel {
+        let _ = channel_id.say(&ctx.http, format!(":radio: Autoplay: **{}**.", title)).await;
+    }
+}
+
+/// Set once Spotify refused recommendations. The endpoint is deprecated and refuses newer apps, so
+/// autoplay sticks to YouTube mixes from then on instead of failing on every track.
+static SPOTIFY_RECOMMENDATIONS_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Picks a track related to `seed` that was not played recently. Spotify recommendations seeded
+/// from the history come first when Spotify is set up, then the YouTube mix of the last video played.
+async fn related_track(ctx: &Context, guild_id: GuildId, seed: &TrackInfo) -> Option<(AuxMetadata, TrackSource)> {
+    let history = {
+        let data = ctx.data.read().await;
+        data.get::<HistoryKey>()
+            .cloned()
+            .expect("History placed in at init.")
+    }.read().await.entries(guild_id);
+
+    let mut played = vec![seed.metadata.clone()];
+    played.extend(history.iter().map(|entry| entry.metadata()));
+    let is_recent = |metadata: &AuxMetadata| played.iter().any(|recent| {
+        (recent.source_url.is_some() && recent.source_url == metadata.source_url)
+            || track_title(recent).eq_ignore_ascii_case(&track_title(metadata))
+    });
+
+    let spotify = {
+        let data = ctx.data.read().await;
+        data.get::<SpotifyKey>().cloned()
+    };
+    let seeds: Vec<_> = played.iter()
+        .filter_map(|metadata| match spotify::parse_link(metadata.source_url.as_deref()?) {
+            Some(SpotifyLink::Track(id)) => Some(id),
+            _ => None,
+        })
+        .take(5)
+        .collect();
+
+    let spotify = spotify.filter(|_| !seeds.is_empty() && !SPOTIFY_RECOMMENDATIONS_FAILED.load(Ordering::Relaxed));
+    if let Some(spotify) = spotify {
+        match spotify::recommendations(&spotify, seeds).await {
+            Ok(tracks) => {
+                if let Some(track) = tracks.into_iter().find(|track| !is_recent(&track.metadata)) {
+                    return Some((track.metadata, TrackSource::Search(track.query)));
+                }
+            },
+            Err(error) => {
+                if !SPOTIFY_RECOMMENDATIONS_FAILED.swap(true, Ordering::Relaxed) {
+                    println!("Failed to get Spotify recommendations, using YouTube mixes until restarted: {:?}", error);
+                }
+            },
+        }
+    }
+
+    let seed_url = played.iter()
+        .filter_map(|metadata| metadata.source_url.as_deref())
+        .find(|url| youtube::video_id(url).is_some())?;
+
+    match youtube::mix_entries(seed_url).await {
+        Ok(entries) => entries.into_iter()
+            .find(|entry| !is_recent(&entry.metadata))
+            .map(|entry| (entry.metadata, TrackSource::Url(entry.url))),
+        Err(error) => {
+            println!("Failed to list the mix of {}: {:?}", seed_url, error);
+            None
+        },
+    }
+}
+
+fn describe_play_error(error: &PlayError) -> String {
+    match error {
+        PlayError::Create(error) => match error.as_ref() {
+            AudioStreamError::Fail(reason) => {
+                // yt-dlp reports failures such as geo-blocks on a line starting with "ERROR:".
+                let reason = reason.to_string();
+                let line = reason.lines()
+                    .find_map(|line| line.trim().strip_prefix("ERROR:"))
+                    .unwrap_or(reason.as_str())
+                    .trim();
+
+                line.chars().take(200).collect()
+            },
+            _ => String::from("the source could not be loaded"),
+        },
+        PlayError::Parse(_) => String::from("the format is not supported"),
+        PlayError::Decode(_) => String::from("the audio could not be decoded"),
+        PlayError::Seek(_) => String::from("seeking failed"),
+        _ => String::from("unknown error"),
+    }
+}
+
 #[group]
 #[owners_only]
 #[summary = "Commands for server owners."]
-#[commands(create_channel)]
+#[commands(create_channel, cache_stats)]
 struct Owner;
 
 // #[group]
@@ -65,7 +433,9 @@ struct Owner;
 
 #[group]
 #[summary = "Commands for all users."]
-#[commands(ping, join, leave, play, skip, stop, queue, reset_queue, about, am_i_admin, przepros)]
+#[commands(ping, join, leave, play, playattachment, skip, stop, queue, reset_queue, nowplaying, pause, resume, seek,
+volume, loop_mode, shuffle, remove, move_track, playnext, skipto, always_on, autoresume, autoplay, search, dj_role, limits, filter, history, replay, playlist, library, playfile, sb, about,
+am_i_admin, przepros)]
 struct General;
 
 #[help]
@@ -122,257 +492,3730 @@ async fn get_http_client(ctx: &Context) -> Client {
         .expect("Guaranteed to exist in typemap")
 }
 
-#[command]
-async fn about(ctx: &Context, msg: &Message) -> CommandResult {
-    msg.channel_id.say(&ctx.http, "Alfred. Poproś o co chesz, paniczu.").await?;
-
-    Ok(())
+fn track_title(metadata: &AuxMetadata) -> String {
+    metadata.title.clone()
+        .or_else(|| metadata.track.clone())
+        .or_else(|| metadata.source_url.clone())
+        .unwrap_or_else(|| String::from("Unknown track"))
 }
 
-#[command]
-async fn am_i_admin(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    if let Some(member) = &msg.member {
-        for role in &member.roles {
-            if role
-                .to_role_cached(&ctx.cache)
-                .map_or(false, |r| r.has_permission(Permissions::ADMINISTRATOR)) {
-                msg.channel_id.say(&ctx.http, "Yes, you are.").await?;
+async fn guild_states(ctx: &Context) -> GuildStates {
+    let data = ctx.data.read().await;
+    data.get::<GuildStateKey>()
+        .cloned()
+        .expect("Guild states placed in at init.")
+}
 
-                return Ok(());
-            }
-        }
-    }
+async fn guild_filter(ctx: &Context, guild_id: GuildId) -> Filter {
+    guild_states(ctx).await
+        .read().await
+        .get(&guild_id)
+        .map(|state| state.filter)
+        .unwrap_or_default()
+}
 
-    msg.channel_id.say(&ctx.http, "No, you are not..").await?;
+/// Position of `track` in its source, accounting for where it started and the filter's tempo.
+pub(crate) async fn track_position(track: &TrackHandle, info: &TrackInfo) -> Duration {
+    let played = match track.get_info().await {
+        Ok(state) => state.position,
+        Err(_) => Duration::ZERO,
+    };
 
-    Ok(())
+    info.start + played.mul_f64(info.filter.tempo())
 }
 
-#[command]
-async fn ping(ctx: &Context, msg: &Message) -> CommandResult {
-    if let Err(error) = msg.channel_id.say(&ctx.http, "Pong").await {
-        println!("Error sending message: {}.", error);
-    }
-    return Ok(());
-}
+/// Replaces the current track with a fresh input at `position`, using the guild's current filter.
+async fn restart_current(ctx: &Context, guild_id: GuildId, position: Duration) -> Result<(), String> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock,
+        None => return Err(String::from("Not in voice channel.")),
+    };
 
-// #[command]
-// async fn jd(ctx: &Context, msg: &Message) -> CommandResult {
-//     if let Err(error) = msg.channel_id.send_message(&ctx.http, |m: MessageBuilder| {
-//         m.("JD!").add_file(Path::new("media/dis.png"))
-//     }).await {
-//         println!("Error sending message: {:?}.", error);
-//     }
-//
-//     Ok(())
-// }
+    let filter = guild_filter(ctx, guild_id).await;
+    let http_client = get_http_client(ctx).await;
+    let cache = audio_cache(ctx).await;
 
-#[command]
-async fn create_channel(ctx: &Context, msg: &Message) -> CommandResult {
-    msg.reply(ctx,"Not yet implemented.").await?;
-    !unimplemented!()
-}
+    let mut handler = handler_lock.lock().await;
+    let current = match handler.queue().current() {
+        Some(current) => current,
+        None => return Ok(()),
+    };
 
-// #[command]
-// async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
-//     let data = ctx.data.read().await;
-//     let shard_manager = match data.get::<ShardManagerContainer>() {
-//         Some(v) => v,
-//         None => {
-//             msg.reply(ctx, "There was a problem getting the shard manager. (Nie przejmuj się tym.)").await?;
-//
-//             return Ok(());
-//         },
-//     };
-//
-//     let manger = shard_manager.lock().await;
-//     let runners = manger.runners.lock().await;
-//
-//     let runner = match runners.get(&ShardId(ctx.shard_id)) {
-//         Some(runner) => runner,
-//         None => {
-//             msg.reply(ctx, "No shard found.").await?;
-//
-//             return Ok(());
-//         },
-//     };
-//
-//     msg.reply(ctx, &format!("The shard latency is {:?}", runner.latency)).await?;
-//
-//     Ok(())
-// }
+    let mut info = get_track_info(&current).await;
+    let source = match &info.source {
+        Some(source) => source.to_input(http_client),
+        None => return Err(String::from(":warning: This track cannot be restarted.")),
+    };
+    let volume = match current.get_info().await {
+        Ok(state) => state.volume,
+        Err(_) => 1.0,
+    };
 
-#[command]
-async fn przepros(ctx:  &Context, msg: &Message) -> CommandResult {
-    msg.reply(ctx, "Przepraszam, mój panie.").await?;
+    // Filtered inputs start at the position themselves, plain ones seek once ready.
+    info.filter = Filter::Off;
+    info.start = if filter == Filter::Off { Duration::ZERO } else { position };
+    let source = info.apply_filter(source, filter);
+    let track = enqueue_track(&mut handler, &cache, source, info, volume).await;
+    if filter == Filter::Off && !position.is_zero() {
+        let _ = track.seek(position);
+    }
+
+    let queue = handler.queue();
+    queue.modify_queue(|queue| {
+        if let Some(restarted) = queue.pop_back() {
+            queue.insert(1, restarted);
+        }
+    });
+    mark_replaced(&current).await;
+    let _ = queue.skip();
 
     Ok(())
 }
 
-#[command]
-async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let url = match args.single::<String>() {
-        Ok(url) => url,
-        Err(_) => {
-            msg.channel_id.say(&ctx.http, ":warning: Use command like this: play <url>");
-            return Ok(());
-        }
+/// Rebuilds the tracks waiting behind the current one with `filter`, keeping their places in the queue.
+async fn refilter_queue(ctx: &Context, guild_id: GuildId, filter: Filter) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock,
+        None => return,
     };
 
-    let do_search = !url.starts_with("http");
+    let http_client = get_http_client(ctx).await;
+    let cache = audio_cache(ctx).await;
 
-    let guild_id = msg.guild_id.unwrap();
+    let mut handler = handler_lock.lock().await;
+    let mut rebuilt = Vec::new();
+    for track in handler.queue().current_queue().into_iter().skip(1) {
+        let mut info = get_track_info(&track).await;
+        let source = match &info.source {
+            Some(source) if info.filter != filter => source.to_input(http_client.clone()),
+            _ => continue,
+        };
+        let volume = match track.get_info().await {
+            Ok(state) => state.volume,
+            Err(_) => 1.0,
+        };
+
+        info.filter = Filter::Off;
+        let source = info.apply_filter(source, filter);
+        enqueue_track(&mut handler, &cache, source, info, volume).await;
+        rebuilt.push(track);
+    }
+
+    // The rebuilt tracks were appended in order, each one takes the place of the track it replaces.
+    let stale = handler.queue().modify_queue(|queue| {
+        let appended = queue.split_off(queue.len() - rebuilt.len());
+        let mut stale = Vec::with_capacity(rebuilt.len());
+        for (old, queued) in rebuilt.iter().zip(appended) {
+            // Tracks that started playing in the meantime keep their input.
+            match queue.iter().skip(1).position(|entry| entry.uuid() == old.uuid()) {
+                Some(index) => stale.push(std::mem::replace(&mut queue[index + 1], queued).handle()),
+                None => stale.push(queued.handle()),
+            }
+        }
+        stale
+    });
+    for track in stale {
+        mark_replaced(&track).await;
+        let _ = track.stop();
+    }
+}
 
-    let http_client = {
+async fn guild_loop_mode(ctx: &Context, guild_id: GuildId) -> LoopMode {
+    guild_states(ctx).await
+        .read().await
+        .get(&guild_id)
+        .map(|state| state.loop_mode)
+        .unwrap_or_default()
+}
+
+async fn guild_settings(ctx: &Context, guild_id: GuildId) -> GuildSettings {
+    let settings = {
         let data = ctx.data.read().await;
-        data.get::<HttpKey>()
+        data.get::<SettingsKey>()
             .cloned()
-            .expect("Guaranteed to exist in the typemap.")
+            .expect("Settings placed in at init.")
     };
 
-    let manager = songbird::get(ctx).await
-        .expect("Songbird Voice client placed in at init.")
-        .clone();
+    let settings = settings.read().await;
+    settings.guild(guild_id)
+}
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
+/// Applies `change` to the guild's settings and writes them to disk.
+async fn update_guild_settings(ctx: &Context, guild_id: GuildId, change: impl FnOnce(&mut GuildSettings)) -> GuildSettings {
+    let settings = {
+        let data = ctx.data.read().await;
+        data.get::<SettingsKey>()
+            .cloned()
+            .expect("Settings placed in at init.")
+    };
 
-        let mut source = if do_search {
-            YoutubeDl::new_search(http_client, url)
-        } else {
-            YoutubeDl::new(http_client, url)
-        };
+    let mut settings = settings.write().await;
+    change(settings.guild_mut(guild_id));
+    if let Err(error) = settings.save().await {
+        println!("Failed to save guild settings: {}", error);
+    }
 
-        handler.stop();
-        handler.queue().stop();
-        handler.enqueue_input(source.clone().into()).await;
-        //handler.play_input(source.clone().into());
+    settings.guild(guild_id)
+}
 
-        msg.channel_id.say(&ctx.http,
-        format!(/*Add a song title*/"Playing the song, position in the queue: position {}", handler.queue().len())
-        ).await;
+fn member_roles(msg: &Message) -> &[RoleId] {
+    msg.member.as_ref().map_or(&[], |member| member.roles.as_slice())
+}
 
-        handler.queue().resume().expect("Couldn't resume queue.");
-    } else {
-        msg.channel_id.say(&ctx.http, ":warning: Error sourcing ffmpeg.").await;
+/// Members who may skip and manage the queue without asking others: admins and the guild's DJ role.
+async fn is_dj(ctx: &Context, msg: &Message) -> bool {
+    has_dj_role(ctx, msg.guild_id, member_roles(msg)).await
+}
+
+async fn has_dj_role(ctx: &Context, guild_id: Option<GuildId>, roles: &[RoleId]) -> bool {
+    if has_admin_role(ctx, roles) {
+        return true;
     }
 
-    Ok(())
+    let dj_role = match guild_id {
+        Some(guild_id) => guild_settings(ctx, guild_id).await.dj_role,
+        None => None,
+    };
+
+    dj_role.is_some_and(|dj_role| roles.contains(&dj_role))
 }
 
-#[command]
-async fn stop(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    let guild_id = msg.guild_id.unwrap();
+/// Destructive commands are open to everyone until the guild sets a DJ role.
+async fn dj_allowed(ctx: &Context, msg: &Message) -> bool {
+    roles_dj_allowed(ctx, msg.guild_id, member_roles(msg)).await
+}
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+async fn roles_dj_allowed(ctx: &Context, guild_id: Option<GuildId>, roles: &[RoleId]) -> bool {
+    let dj_role = match guild_id {
+        Some(guild_id) => guild_settings(ctx, guild_id).await.dj_role,
+        None => None,
+    };
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
-        handler.queue().stop();
-        handler.stop();
+    dj_role.is_none() || has_dj_role(ctx, guild_id, roles).await
+}
 
-        msg.channel_id.say(&ctx.http, "Playback stopped, queue cleared.").await;
-    } else {
-        msg.channel_id.say(&ctx.http, "Not in voice channel.").await;
+#[check]
+#[name = "DJ"]
+async fn dj_check(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    _: &CommandOptions) -> Result<(), Reason> {
+    if !dj_allowed(ctx, msg).await {
+        return Err(Reason::User(String::from(":warning: This command needs the DJ role.")));
     }
 
     Ok(())
 }
 
-#[command]
-async fn skip(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    let guild_id = msg.guild_id.unwrap();
+/// Non-bot members in the bot's voice channel, from the guild cache.
+fn listeners(ctx: &Context, guild_id: GuildId, channel: songbird::id::ChannelId) -> Vec<UserId> {
+    let bot_id = ctx.cache.current_user().id;
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+    ctx.cache.guild(guild_id)
+        .map(|guild| guild.voice_states.values()
+            .filter(|voice_state| voice_state.user_id != bot_id
+                && voice_state.channel_id.is_some_and(|id| songbird::id::ChannelId::from(id) == channel)
+                && !voice_state.member.as_ref().is_some_and(|member| member.user.bot))
+            .map(|voice_state| voice_state.user_id)
+            .collect())
+        .unwrap_or_default()
+}
+
+fn is_admin(ctx: &Context, msg: &Message) -> bool {
+    has_admin_role(ctx, member_roles(msg))
+}
+
+fn has_admin_role(ctx: &Context, roles: &[RoleId]) -> bool {
+    for role in roles {
+        if role
+            .to_role_cached(&ctx.cache)
+            .map_or(false, |r| r.has_permission(Permissions::ADMINISTRATOR)) {
+            return true;
+        }
+    }
+
+    false
+}
+
+async fn mark_replaced(track: &TrackHandle) {
+    if let Some(info) = track.typemap().write().await.get_mut::<TrackInfoKey>() {
+        info.replaced = true;
+    }
+}
+
+/// Soundboard clips play outside the queue and carry no `TrackInfo`.
+pub(crate) async fn is_queued_track(track: &TrackHandle) -> bool {
+    track.typemap().read().await.contains_key::<TrackInfoKey>()
+}
+
+pub(crate) async fn get_track_info(track: &TrackHandle) -> TrackInfo {
+    let typemap = track.typemap().read().await;
+    typemap.get::<TrackInfoKey>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Sources resolved from a single `play`/`queue` argument, named after their album or playlist.
+struct ResolvedSources {
+    name: Option<String>,
+    sources: Vec<(Input, AuxMetadata, TrackSource)>,
+}
+
+/// Turns a URL, Spotify link or search query into yt-dlp sources. `Err` holds a message for the user.
+///
+/// Albums and playlists are capped at the configured playlist limit, optionally shuffled beforehand.
+async fn resolve_sources(ctx: &Context, url: String, shuffle: bool) -> Result<ResolvedSources, String> {
+    let mut resolved = resolve_all_sources(ctx, url).await?;
+
+    if resolved.name.is_some() {
+        let limit = {
+            let data = ctx.data.read().await;
+            data.get::<ConfigKey>()
+                .expect("Config placed in at init.")
+                .playlist_limit()
+        };
+
+        if shuffle {
+            resolved.sources.shuffle(&mut rand::thread_rng());
+        }
+        resolved.sources.truncate(limit);
+    }
+
+    Ok(resolved)
+}
+
+async fn resolve_all_sources(ctx: &Context, url: String) -> Result<ResolvedSources, String> {
+    let http_client = get_http_client(ctx).await;
+
+    if let Some(playlist) = youtube::playlist_url(&url) {
+        let (name, entries) = match youtube::playlist_entries(&playlist).await {
+            Ok(playlist) => playlist,
+            Err(error) => {
+                println!("Failed to list playlist {}: {:?}", url, error);
+                return Err(String::from(":warning: Could not load the playlist."));
+            },
+        };
+
+        let sources = entries.into_iter()
+            .map(|entry| {
+                let source = TrackSource::Url(entry.url);
+                (source.to_input(http_client.clone()), entry.metadata, source)
+            })
+            .collect();
+
+        return Ok(ResolvedSources { name: Some(name.unwrap_or(String::from("playlist"))), sources });
+    }
+
+    if let Some(link) = spotify::parse_link(&url) {
+        let spotify = {
+            let data = ctx.data.read().await;
+            data.get::<SpotifyKey>().cloned()
+        };
+        let spotify = match spotify {
+            Some(spotify) => spotify,
+            None => return Err(String::from(":warning: Spotify is not configured.")),
+        };
+
+        let (name, tracks) = match spotify::resolve(&spotify, &link).await {
+            Ok(resolved) => resolved,
+            Err(error) => {
+                println!("Failed to resolve Spotify link {}: {:?}", url, error);
+                return Err(String::from(":warning: Could not resolve the Spotify link."));
+            },
+        };
+
+        let sources = tracks.into_iter()
+            .map(|track| {
+                let source = TrackSource::Search(track.query);
+                (source.to_input(http_client.clone()), track.metadata, source)
+            })
+            .collect();
+
+        return Ok(ResolvedSources { name, sources });
+    }
+
+    let cached = match canonical_url(&url) {
+        Some(key) => audio_cache(ctx).await.read().await.peek(&key),
+        None => None,
+    };
+    if let Some(entry) = cached {
+        let source = TrackSource::Url(url);
+        return Ok(ResolvedSources {
+            name: None,
+            sources: vec![(source.to_input(http_client), entry.track.metadata(), source)],
+        });
+    }
+
+    let (mut source, track_source) = if url.starts_with("http") {
+        (YoutubeDl::new(http_client, url.clone()), TrackSource::Url(url))
+    } else {
+        (YoutubeDl::new_search(http_client, url.clone()), TrackSource::Search(url))
+    };
+    let metadata = source.aux_metadata().await.unwrap_or_default();
+
+    Ok(ResolvedSources { name: None, sources: vec![(source.into(), metadata, track_source)] })
+}
+
+/// Downloads the audio attachments of `msg`, or of the message it replies to, and probes them.
+async fn resolve_attachments(ctx: &Context, msg: &Message) -> Result<ResolvedSources, String> {
+    let attachments = match &msg.referenced_message {
+        Some(referenced) if msg.attachments.is_empty() => &referenced.attachments,
+        _ => &msg.attachments,
+    };
+
+    if attachments.is_empty() {
+        return Err(String::from(":warning: Attach an audio file or reply to a message with one."));
+    }
+
+    let (max_size, allowed_types) = {
+        let data = ctx.data.read().await;
+        let config = data.get::<ConfigKey>().expect("Config placed in at init.");
+        (config.attachment_max_size(), config.attachment_types().clone())
+    };
+    let http_client = get_http_client(ctx).await;
+
+    let mut sources = Vec::with_capacity(attachments.len());
+    for attachment in attachments {
+        let content_type = attachment.content_type.as_deref().unwrap_or_default();
+        if !allowed_types.iter().any(|allowed| content_type.starts_with(allowed.as_str())) {
+            return Err(format!(":warning: `{}` is not an audio file.", attachment.filename));
+        }
+
+        if u64::from(attachment.size) > max_size {
+            return Err(format!(":warning: `{}` is larger than the {} limit.",
+                attachment.filename, utils::to_size(max_size)));
+        }
+
+        let bytes = match http_client.get(&attachment.url).send().await {
+            Ok(response) => response.bytes().await,
+            Err(error) => Err(error),
+        };
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                println!("Failed to download attachment {}: {:?}", attachment.url, error);
+                return Err(format!(":warning: Could not download `{}`.", attachment.filename));
+            },
+        };
+
+        let extension = Path::new(&attachment.filename)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(String::from);
+        let probe_bytes = bytes.clone();
+        let metadata = tokio::task::spawn_blocking(move || {
+            library::probe(Box::new(Cursor::new(probe_bytes)), extension.as_deref())
+        }).await.ok().flatten();
+
+        let mut metadata = match metadata {
+            Some(metadata) => metadata,
+            None => return Err(format!(":warning: `{}` is not in a supported audio format.", attachment.filename)),
+        };
+        if metadata.title.is_none() {
+            metadata.title = Some(attachment.filename.clone());
+        }
+        metadata.source_url = Some(attachment.url.clone());
+
+        sources.push((Input::from(bytes), metadata, TrackSource::Http(attachment.url.clone())));
+    }
+
+    let name = (sources.len() > 1).then(|| String::from("attachments"));
+
+    Ok(ResolvedSources { name, sources })
+}
+
+/// Enqueues without probing the source again: preloading is timed from the metadata we already have.
+async fn enqueue_track(
+    handler: &mut Call,
+    cache: &Arc<RwLock<AudioCache>>,
+    source: impl Into<Input>,
+    info: TrackInfo,
+    volume: f32,
+) -> TrackHandle {
+    let source = cached_source(cache, source.into(), &info).await;
+    let preload_time = info.metadata.duration
+        .map(|duration| duration.saturating_sub(Duration::from_secs(5)));
+
+    let track = handler.enqueue_with_preload(Track::from(source).volume(volume), preload_time);
+    track.typemap().write().await.insert::<TrackInfoKey>(info);
+
+    track
+}
+
+async fn audio_cache(ctx: &Context) -> Arc<RwLock<AudioCache>> {
+    let data = ctx.data.read().await;
+    data.get::<AudioCacheKey>()
+        .cloned()
+        .expect("Audio cache placed in at init.")
+}
+
+/// Lets `source` be swapped for the cached download of the track once it is about to play. The
+/// cache is only looked at then, so downloads evicted in the meantime are not missed.
+async fn cached_source(cache: &Arc<RwLock<AudioCache>>, source: Input, info: &TrackInfo) -> Input {
+    let key = match cache_key(info) {
+        Some(key) if cache.read().await.enabled() => key,
+        _ => return source,
+    };
+
+    match source {
+        Input::Lazy(source) => CachedInput {
+            cache: cache.clone(),
+            key,
+            filter: info.filter,
+            start: info.start,
+            source: Some(source),
+        }.into(),
+        source => source,
+    }
+}
+
+/// Connects to `channel_id` after checking permissions. `Err` holds a message for the user.
+async fn join_channel(ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> Result<Arc<Mutex<Call>>, String> {
+    let bot_id = ctx.cache.current_user().id;
+    let permissions = ctx.cache.guild(guild_id).and_then(|guild| {
+        let channel = guild.channels.get(&channel_id)?;
+        let member = guild.members.get(&bot_id)?;
+
+        Some(guild.user_permissions_in(channel, member))
+    });
+
+    if permissions.is_some_and(|permissions| !permissions.connect() || !permissions.speak()) {
+        return Err(format!(":warning: I need the Connect and Speak permissions in <#{}>.", channel_id));
+    }
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+
+    if manager.get(guild_id).is_none() {
+        let handler_lock = manager.get_or_insert(guild_id);
+        let mut handler = handler_lock.lock().await;
+        let notifier = TrackErrorNotifier {
+            http: ctx.http.clone(),
+            queue: handler.queue().clone(),
+        };
+        handler.add_global_event(TrackEvent::Error.into(), notifier);
+
+        for event in [TrackEvent::Play, TrackEvent::End] {
+            let looper = TrackLooper {
+                manager: manager.clone(),
+                guild_id,
+                states: guild_states(ctx).await,
+                http_client: get_http_client(ctx).await,
+                cache: audio_cache(ctx).await,
+            };
+            handler.add_global_event(event.into(), looper);
+        }
+
+        let history = {
+            let data = ctx.data.read().await;
+            data.get::<HistoryKey>()
+                .cloned()
+                .expect("History placed in at init.")
+        };
+        handler.add_global_event(TrackEvent::End.into(), HistoryRecorder { guild_id, history });
+
+        let snapshotter = QueueSnapshotter {
+            guild_id,
+            manager: manager.clone(),
+            states: guild_states(ctx).await,
+            snapshots: snapshot_store(ctx).await,
+        };
+        handler.add_global_event(TrackEvent::Play.into(), snapshotter);
+        for event in [TrackEvent::End, TrackEvent::Error] {
+            handler.add_global_event(event.into(), Autoplayer { ctx: ctx.clone(), guild_id });
+        }
+        handler.add_global_event(TrackEvent::End.into(), AudioCacher { cache: audio_cache(ctx).await });
+        for event in [TrackEvent::Play, TrackEvent::Pause, TrackEvent::End] {
+            handler.add_global_event(event.into(), NowPlayingUpdater { ctx: ctx.clone(), guild_id });
+        }
+
+        tokio::spawn(watch_idle(ctx.clone(), guild_id, Arc::downgrade(&handler_lock)));
+    }
+
+    match manager.join(guild_id, channel_id).await {
+        Ok(handler_lock) => Ok(handler_lock),
+        Err(error) => {
+            println!("Failed to join channel {}: {:?}", channel_id, error);
+            Err(String::from(":warning: Error joining channel. Please ensure I have the correct permissions."))
+        },
+    }
+}
+
+async fn idle_timeout(ctx: &Context) -> Duration {
+    let data = ctx.data.read().await;
+    let config = data.get::<ConfigKey>().expect("Config placed in at init.");
+    Duration::from_secs(config.idle_timeout())
+}
+
+/// Leaves the voice channel once the queue has been empty for the configured idle timeout. The
+/// driver stops ticking without tracks, so this runs on its own timer. Being left alone is
+/// handled by `update_alone_timer` as members come and go instead.
+async fn watch_idle(ctx: Context, guild_id: GuildId, call: Weak<Mutex<Call>>) {
+    let timeout = idle_timeout(&ctx).await;
+    let manager = songbird::get(&ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+    let mut idle_since: Option<Instant> = None;
+    loop {
+        tokio::time::sleep(Duration::from_secs(15)).await;
+
+        // Stop once this call has been left, even if the bot joined again in the meantime.
+        let handler_lock = match (call.upgrade(), manager.get(guild_id)) {
+            (Some(call), Some(current)) if Arc::ptr_eq(&call, &current) => call,
+            _ => return,
+        };
+
+        let (channel, queue_empty) = {
+            let handler = handler_lock.lock().await;
+            (handler.current_channel(), handler.queue().is_empty())
+        };
+        let channel = match channel {
+            Some(channel) => channel,
+            None => continue,
+        };
+
+        if !queue_empty || guild_settings(&ctx, guild_id).await.always_on {
+            idle_since = None;
+            continue;
+        }
+
+        let since = *idle_since.get_or_insert_with(Instant::now);
+        if since.elapsed() < timeout {
+            continue;
+        }
+
+        let text_channel = guild_states(&ctx).await
+            .read().await
+            .get(&guild_id)
+            .and_then(|state| state.text_channel);
+        if let Some(text_channel) = text_channel {
+            let _ = text_channel.say(&ctx.http, format!(
+                ":wave: Left <#{}>, nothing is playing for {}.", channel, utils::to_time(timeout.as_secs())
+            )).await;
+        }
+
+        drop(handler_lock);
+        leave_voice(&ctx, guild_id).await;
+        return;
+    }
+}
+
+/// Starts counting down to leaving once nobody but bots is left in the bot's voice channel, and
+/// stops when someone joins back or 24/7 mode is turned on. Run on every voice state change.
+pub(crate) async fn update_alone_timer(ctx: &Context, guild_id: GuildId) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+
+    let channel = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.current_channel(),
+        None => None,
+    };
+    let alone = channel.is_some_and(|channel| listeners(ctx, guild_id, channel).is_empty());
+    let leave = alone && !guild_settings(ctx, guild_id).await.always_on;
+
+    let states = guild_states(ctx).await;
+    let mut states = states.write().await;
+    if leave {
+        let state = states.entry(guild_id).or_default();
+        if state.alone_timer.is_none() {
+            state.alone_timer = Some(tokio::spawn(leave_when_alone(ctx.clone(), guild_id)).abort_handle());
+        }
+    } else if let Some(timer) = states.get_mut(&guild_id).and_then(|state| state.alone_timer.take()) {
+        timer.abort();
+    }
+}
+
+async fn leave_when_alone(ctx: Context, guild_id: GuildId) {
+    let timeout = idle_timeout(&ctx).await;
+    tokio::time::sleep(timeout).await;
+
+    // Cleared before leaving, which would otherwise abort this task halfway through.
+    let text_channel = guild_states(&ctx).await
+        .write().await
+        .get_mut(&guild_id)
+        .and_then(|state| {
+            state.alone_timer = None;
+            state.text_channel
+        });
+
+    let manager = songbird::get(&ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+    let channel = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.current_channel(),
+        None => return,
+    };
+    // Someone may have joined back right as the timer ran out.
+    if channel.is_some_and(|channel| !listeners(&ctx, guild_id, channel).is_empty()) {
+        return;
+    }
+    if let (Some(channel), Some(text_channel)) = (channel, text_channel) {
+        let _ = text_channel.say(&ctx.http, format!(
+            ":wave: Left <#{}>, everyone left for {}.", channel, utils::to_time(timeout.as_secs())
+        )).await;
+    }
+
+    leave_voice(&ctx, guild_id).await;
+}
+
+/// Disconnects from the guild's voice channel and forgets its playback state.
+pub(crate) async fn leave_voice(ctx: &Context, guild_id: GuildId) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        handler_lock.lock().await.queue().stop();
+
+        if let Err(error) = manager.remove(guild_id).await {
+            println!("Failed to leave voice in {}: {:?}", guild_id, error);
+        }
+    }
+
+    finish_now_playing(ctx, guild_id).await;
+    let state = guild_states(ctx).await.write().await.remove(&guild_id);
+    if let Some(timer) = state.and_then(|state| state.alone_timer) {
+        timer.abort();
+    }
+    forget_snapshot(ctx, guild_id).await;
+}
+
+async fn snapshot_store(ctx: &Context) -> Arc<RwLock<Snapshots>> {
+    let data = ctx.data.read().await;
+    data.get::<SnapshotsKey>()
+        .cloned()
+        .expect("Snapshots placed in at init.")
+}
+
+/// Saves the guild's queue shortly, so it can be resumed after a restart.
+pub(crate) async fn snapshot_guild(ctx: &Context, guild_id: GuildId) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+
+    let snapshots = snapshot_store(ctx).await;
+    schedule_snapshot(&manager, &guild_states(ctx).await, &snapshots, guild_id).await;
+}
+
+async fn forget_snapshot(ctx: &Context, guild_id: GuildId) {
+    let snapshots = snapshot_store(ctx).await;
+    let mut snapshots = snapshots.write().await;
+    if snapshots.set(guild_id, None) {
+        if let Err(error) = snapshots.save().await {
+            println!("Failed to save queue snapshots: {}", error);
+        }
+    }
+}
+
+/// Forgets the guild's snapshot only if it is still `offered`, so that a queue started since keeps its own.
+async fn forget_offered_snapshot(ctx: &Context, guild_id: GuildId, offered: &QueueSnapshot) {
+    let snapshots = snapshot_store(ctx).await;
+    let mut snapshots = snapshots.write().await;
+    if snapshots.forget_if(guild_id, offered) {
+        if let Err(error) = snapshots.save().await {
+            println!("Failed to save queue snapshots: {}", error);
+        }
+    }
+}
+
+/// Picks up the queues saved before the last shutdown. Guilds with auto-resume get them back right
+/// away, the others are asked in the channel they last used.
+pub(crate) async fn restore_queues(ctx: &Context) {
+    let snapshots = snapshot_store(ctx).await.read().await.all();
+
+    for (guild_id, snapshot) in snapshots {
+        let text_channel = snapshot.text_channel;
+
+        if guild_settings(ctx, guild_id).await.auto_resume {
+            let reply = match resume_snapshot(ctx, guild_id, snapshot).await {
+                Ok(reply) => format!(":arrows_counterclockwise: Back after a restart. {}", reply),
+                Err(reason) => reason,
+            };
+            if let Some(channel_id) = text_channel {
+                let _ = channel_id.say(&ctx.http, reply).await;
+            }
+            continue;
+        }
+
+        match text_channel {
+            Some(channel_id) => {
+                tokio::spawn(offer_resume(ctx.clone(), guild_id, channel_id, snapshot));
+            },
+            None => forget_snapshot(ctx, guild_id).await,
+        }
+    }
+}
+
+/// Asks whether to resume a saved queue, forgetting it when declined or left unanswered.
+async fn offer_resume(ctx: Context, guild_id: GuildId, channel_id: ChannelId, snapshot: QueueSnapshot) {
+    let description = format!("I was restarted while playing **{}** with {} queued in <#{}>.",
+        track_title(&snapshot.tracks[0].track.metadata()),
+        count_tracks(snapshot.tracks.len() - 1),
+        snapshot.voice_channel);
+    let embed = CreateEmbed::new()
+        .title("Resume the queue?")
+        .description(&description)
+        .footer(CreateEmbedFooter::new(format!("The offer expires in {} minutes", RESUME_OFFER_TIMEOUT.as_secs() / 60)));
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("snapshot_resume").label("Resume"),
+        CreateButton::new("snapshot_discard").label("Discard").style(ButtonStyle::Secondary),
+    ])];
+
+    let mut message = match channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed).components(buttons)).await {
+        Ok(message) => message,
+        Err(error) => {
+            println!("Failed to offer resuming the queue in {}: {:?}", guild_id, error);
+            forget_offered_snapshot(&ctx, guild_id, &snapshot).await;
+            return;
+        },
+    };
+
+    // Answering is up to the same members who may stop the music.
+    let mut interactions = message.await_component_interactions(&ctx.shard)
+        .timeout(RESUME_OFFER_TIMEOUT)
+        .stream();
+    let mut interaction = None;
+    while let Some(clicked) = interactions.next().await {
+        let roles = clicked.member.as_ref().map_or(&[][..], |member| member.roles.as_slice());
+        if roles_dj_allowed(&ctx, Some(guild_id), roles).await {
+            interaction = Some(clicked);
+            break;
+        }
+
+        let _ = clicked.create_response(&ctx.http, CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new().content(":warning: This needs the DJ role.").ephemeral(true)
+        )).await;
+    }
+
+    let outcome = match interaction {
+        Some(interaction) if interaction.data.custom_id == "snapshot_resume" => {
+            let _ = interaction.defer(&ctx.http).await;
+            match resume_snapshot(&ctx, guild_id, snapshot).await {
+                Ok(reply) => reply,
+                Err(reason) => reason,
+            }
+        },
+        Some(interaction) => {
+            let _ = interaction.defer(&ctx.http).await;
+            forget_offered_snapshot(&ctx, guild_id, &snapshot).await;
+            format!("Discarded by <@{}>.", interaction.user.id)
+        },
+        None => {
+            forget_offered_snapshot(&ctx, guild_id, &snapshot).await;
+            String::from("Nobody answered, the queue was discarded.")
+        },
+    };
+
+    let _ = message.edit(&ctx.http, EditMessage::new()
+        .embed(CreateEmbed::new().description(format!("{}\n{}", description, outcome)))
+        .components(vec![])
+    ).await;
+}
+
+/// Rejoins the saved voice channel and queues the snapshot again, starting the first track where it
+/// left off. `Err` holds a message for the user.
+async fn resume_snapshot(ctx: &Context, guild_id: GuildId, snapshot: QueueSnapshot) -> Result<String, String> {
+    let handler_lock = join_channel(ctx, guild_id, snapshot.voice_channel).await?;
+
+    if !handler_lock.lock().await.queue().is_empty() {
+        forget_offered_snapshot(ctx, guild_id, &snapshot).await;
+        return Err(String::from(":warning: Something else is playing already, the saved queue was discarded."));
+    }
+
+    {
+        let states = guild_states(ctx).await;
+        let mut states = states.write().await;
+        let state = states.entry(guild_id).or_default();
+        state.text_channel = snapshot.text_channel.or(state.text_channel);
+        state.loop_mode = snapshot.loop_mode;
+        state.filter = snapshot.filter;
+    }
+
+    let volume = guild_settings(ctx, guild_id).await.volume;
+    let http_client = get_http_client(ctx).await;
+    let cache = audio_cache(ctx).await;
+    let position = Duration::from_secs(snapshot.position);
+    let title = track_title(&snapshot.tracks[0].track.metadata());
+    let count = snapshot.tracks.len();
+
+    let mut handler = handler_lock.lock().await;
+    for (index, saved) in snapshot.tracks.into_iter().enumerate() {
+        let resume_at = (index == 0).then_some(position).filter(|position| !position.is_zero());
+        let mut info = TrackInfo {
+            metadata: saved.track.metadata(),
+            source: Some(saved.track.source.clone()),
+            requester: saved.requester,
+            channel_id: saved.channel_id,
+            start: Duration::ZERO,
+            filter: Filter::Off,
+            replaced: false,
+        };
+        if let (Some(resume_at), true) = (resume_at, snapshot.filter != Filter::Off) {
+            info.start = resume_at;
+        }
+        let source = info.apply_filter(saved.track.source.to_input(http_client.clone()), snapshot.filter);
+        let track = enqueue_track(&mut handler, &cache, source, info, f32::from(volume) / 100.0).await;
+
+        // Songbird holds the seek until the track is ready, like for linked start times.
+        if let (Some(resume_at), Filter::Off) = (resume_at, snapshot.filter) {
+            let _ = track.seek(resume_at);
+        }
+    }
+    drop(handler);
+
+    snapshot_guild(ctx, guild_id).await;
+
+    Ok(format!("Resumed **{}** at `{}` with {} queued.",
+        title, utils::to_time(snapshot.position), count_tracks(count - 1)))
+}
+
+async fn remember_text_channel(ctx: &Context, msg: &Message) {
+    guild_states(ctx).await
+        .write().await
+        .entry(msg.guild_id.unwrap())
+        .or_default()
+        .text_channel = Some(msg.channel_id);
+}
+
+/// Returns the call in the caller's voice channel, joining it first if needed.
+///
+/// The bot only moves over from another channel of the guild when nothing is queued there.
+async fn ensure_voice(ctx: &Context, msg: &Message) -> Result<Arc<Mutex<Call>>, String> {
+    let guild_id = msg.guild_id.unwrap();
+
+    let caller_channel = msg.guild(&ctx.cache)
+        .and_then(|guild| guild.voice_states.get(&msg.author.id).and_then(|voice_state| voice_state.channel_id));
+
+    let caller_channel = match caller_channel {
+        Some(channel) => channel,
+        None => return Err(String::from(":warning: Join a voice channel first!")),
+    };
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird voice client init.")
+        .clone();
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let (current_channel, is_playing) = {
+            let handler = handler_lock.lock().await;
+            (handler.current_channel(), !handler.queue().is_empty())
+        };
+
+        match current_channel {
+            Some(current) if current == caller_channel.into() => {
+                remember_text_channel(ctx, msg).await;
+                return Ok(handler_lock);
+            },
+            Some(current) if is_playing => {
+                return Err(format!(":warning: I'm already playing in <#{}>, join me there.", current));
+            },
+            _ => {},
+        }
+    }
+
+    let handler_lock = join_channel(ctx, guild_id, caller_channel).await?;
+    remember_text_channel(ctx, msg).await;
+
+    Ok(handler_lock)
+}
+
+async fn library_entries(ctx: &Context) -> Vec<LibraryEntry> {
+    let dir = {
+        let data = ctx.data.read().await;
+        data.get::<ConfigKey>()
+            .expect("Config placed in at init.")
+            .library_dir()
+            .clone()
+    };
+
+    tokio::task::spawn_blocking(move || library::scan(Path::new(&dir)))
+        .await
+        .unwrap_or_default()
+}
+
+fn library_lines<'a>(entries: impl Iterator<Item = (usize, &'a LibraryEntry)>) -> String {
+    let mut lines = String::new();
+    for (index, entry) in entries {
+        let metadata = &entry.metadata;
+        let duration = metadata.duration
+            .map_or(String::from("--:--"), |duration| utils::to_time(duration.as_secs()));
+        let artist = metadata.artist.as_ref()
+            .map_or(String::new(), |artist| format!(" - {}", artist));
+
+        let _ = writeln!(lines, "`{}.` **{}**{} `{}`", index + 1, track_title(metadata), artist, duration);
+    }
+
+    lines
+}
+
+fn queue_page(entries: &[TrackInfo], page: usize, remaining: u64, loop_mode: LoopMode) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let pages = entries.len().div_ceil(QUEUE_PAGE_SIZE).max(1);
+    let page = page.min(pages - 1);
+
+    let mut description = String::new();
+    for (index, entry) in entries.iter().enumerate().skip(page * QUEUE_PAGE_SIZE).take(QUEUE_PAGE_SIZE) {
+        let duration = entry.metadata.duration
+            .map_or(String::from("live"), |duration| utils::to_time(duration.as_secs()));
+        let requester = entry.requester
+            .map_or(String::from("unknown"), |user| format!("<@{}>", user));
+        let marker = if index == 0 { " :arrow_forward:" } else { "" };
+
+        let _ = writeln!(description, "`{}.` **{}** `{}` - {}{}",
+            index + 1, track_title(&entry.metadata), duration, requester, marker);
+    }
+
+    let embed = CreateEmbed::new()
+        .title(format!("Queue ({} tracks)", entries.len()))
+        .description(description)
+        .footer(CreateEmbedFooter::new(
+            format!("Page {}/{} | Remaining time: {} | Loop: {}", page + 1, pages, utils::to_time(remaining), loop_mode)
+        ));
+
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("queue_prev").label("Previous").disabled(page == 0),
+        CreateButton::new("queue_next").label("Next").disabled(page + 1 >= pages),
+    ])];
+
+    (embed, buttons)
+}
+
+/// Looks up the track at the head of the guild's queue. `Err` holds a message for the user.
+async fn current_track(ctx: &Context, guild_id: GuildId) -> Result<TrackHandle, String> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let current = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.queue().current(),
+        None => return Err(String::from("Not in voice channel.")),
+    };
+
+    current.ok_or_else(|| String::from("Nothing is playing right now."))
+}
+
+async fn guild_queue(ctx: &Context, guild_id: GuildId) -> Result<TrackQueue, String> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    match manager.get(guild_id) {
+        Some(handler_lock) => Ok(handler_lock.lock().await.queue().clone()),
+        None => Err(String::from("Not in voice channel.")),
+    }
+}
+
+/// Parses `3` or `2-5` into an inclusive range of queue positions, as numbered in `queue list`.
+fn parse_positions(arg: &str) -> Option<(usize, usize)> {
+    let (start, end) = match arg.split_once('-') {
+        Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+        None => {
+            let position = arg.trim().parse().ok()?;
+            (position, position)
+        },
+    };
+
+    (start <= end).then_some((start, end))
+}
+
+/// Position 1 is the current track, which only `skip` may replace.
+fn check_positions(queue: &TrackQueue, start: usize, end: usize) -> Result<(), String> {
+    if start < 2 {
+        return Err(String::from(":warning: Position 1 is the current track, use `skip` instead."));
+    }
+    if end > queue.len() {
+        return Err(format!(":warning: There are only {} tracks in the queue.", queue.len()));
+    }
+
+    Ok(())
+}
+
+/// Removes queue positions `start..=end` and stops them, unless they belong to someone else
+/// and the author is not a DJ. `Err` holds a message for the user.
+async fn remove_positions(
+    ctx: &Context,
+    msg: &Message,
+    queue: &TrackQueue,
+    start: usize,
+    end: usize,
+) -> Result<Vec<TrackInfo>, String> {
+    // The queue may have moved on since the positions were checked.
+    let tracks = queue.current_queue();
+    let selected = tracks.get(start - 1..end)
+        .ok_or_else(|| format!(":warning: There are only {} tracks in the queue.", tracks.len()))?;
+    let mut entries = Vec::with_capacity(selected.len());
+    for track in selected {
+        entries.push(get_track_info(track).await);
+    }
+
+    let others = entries.iter().any(|entry| entry.requester.is_some_and(|user| user != msg.author.id));
+    if others && !is_dj(ctx, msg).await {
+        return Err(String::from(":warning: You can only remove tracks you requested."));
+    }
+
+    let uuids = selected.iter()
+        .map(|track| track.uuid())
+        .collect::<Vec<_>>();
+    let removed = queue.modify_queue(|queue| {
+        let mut removed = Vec::with_capacity(uuids.len());
+        queue.retain(|queued| {
+            if uuids.contains(&queued.uuid()) {
+                removed.push(queued.handle());
+                return false;
+            }
+            true
+        });
+        removed
+    });
+    for track in removed {
+        let _ = track.stop();
+    }
+
+    Ok(entries)
+}
+
+fn count_tracks(count: usize) -> String {
+    if count == 1 {
+        return String::from("1 track");
+    }
+    format!("{} tracks", count)
+}
+
+/// Drops the sources that break the guild's limits, given what is `queued` already.
+///
+/// Returns a note on what was skipped, or `Err` with a message for the user when nothing is left.
+async fn apply_limits(
+    ctx: &Context,
+    msg: &Message,
+    queued: &[TrackHandle],
+    resolved: &mut ResolvedSources,
+) -> Result<Option<String>, String> {
+    let settings = guild_settings(ctx, msg.guild_id.unwrap()).await;
+
+    let mut user_tracks = 0;
+    for track in queued {
+        if get_track_info(track).await.requester == Some(msg.author.id) {
+            user_tracks += 1;
+        }
+    }
+    let mut queue_length = queued.len();
+
+    let (mut too_long, mut unknown_length, mut over_user, mut over_queue) = (0, 0, 0, 0);
+    resolved.sources.retain(|(_, metadata, _)| {
+        if let Some(max) = settings.max_track_length {
+            // Live streams never end on their own, so they count as too long rather than slip through.
+            match metadata.duration.map(|duration| duration.as_secs()) {
+                Some(length) if length > max => {
+                    too_long += 1;
+                    return false;
+                },
+                None => {
+                    unknown_length += 1;
+                    return false;
+                },
+                Some(_) => {},
+            }
+        }
+        if settings.max_user_tracks.is_some_and(|max| user_tracks >= max) {
+            over_user += 1;
+            return false;
+        }
+        if settings.max_queue_length.is_some_and(|max| queue_length >= max) {
+            over_queue += 1;
+            return false;
+        }
+
+        user_tracks += 1;
+        queue_length += 1;
+        true
+    });
+
+    let mut reasons = Vec::new();
+    if let (Some(max), true) = (settings.max_track_length, too_long > 0) {
+        reasons.push(format!("{} longer than the {} limit", count_tracks(too_long), utils::to_time(max)));
+    }
+    if unknown_length > 0 {
+        reasons.push(format!("{} without a known length, like live streams", count_tracks(unknown_length)));
+    }
+    if let (Some(max), true) = (settings.max_user_tracks, over_user > 0) {
+        reasons.push(format!("{} over the limit of {} queued per person", count_tracks(over_user), max));
+    }
+    if let (Some(max), true) = (settings.max_queue_length, over_queue > 0) {
+        reasons.push(format!("{} over the queue limit of {}", count_tracks(over_queue), max));
+    }
+
+    if reasons.is_empty() {
+        return Ok(None);
+    }
+    if resolved.sources.is_empty() {
+        return Err(format!(":warning: Nothing was added: {}.", reasons.join(", ")));
+    }
+
+    Ok(Some(format!(" Skipped {}.", reasons.join(", "))))
+}
+
+/// Moves the `count` tracks just added to the back of the queue into their turns, so that requesters
+/// take turns. Tracks queued before keep their places, including ones a DJ moved by hand.
+async fn interleave_requesters(queue: &TrackQueue, count: usize) {
+    let tracks = queue.current_queue();
+    let added_from = tracks.len().saturating_sub(count).max(1);
+
+    // A track's turn is how many tracks of the same requester are ahead of it.
+    let mut turns = HashMap::new();
+    let mut order = Vec::with_capacity(tracks.len());
+    for (index, track) in tracks.iter().enumerate().skip(1) {
+        let requester = get_track_info(track).await.requester;
+        let turn = turns.entry(requester).or_insert(0);
+
+        // Each added track goes after the last one whose turn is not later than its own.
+        let position = if index < added_from {
+            order.len()
+        } else {
+            order.iter().rposition(|(earlier, _)| *earlier <= *turn).map_or(0, |position| position + 1)
+        };
+        order.insert(position, (*turn, track.uuid()));
+        *turn += 1;
+    }
+
+    queue.modify_queue(|queue| {
+        if queue.len() < 2 {
+            return;
+        }
+        queue.make_contiguous()[1..].sort_by_key(|queued| {
+            order.iter().position(|(_, uuid)| *uuid == queued.uuid()).unwrap_or(usize::MAX)
+        });
+    });
+}
+
+async fn add_to_queue(
+    ctx: &Context,
+    msg: &Message,
+    handler_lock: Arc<Mutex<Call>>,
+    mut resolved: ResolvedSources,
+    play_next: bool,
+) -> CommandResult {
+    let settings = guild_settings(ctx, msg.guild_id.unwrap()).await;
+    let volume = settings.volume;
+    let filter = guild_filter(ctx, msg.guild_id.unwrap()).await;
+    let cache = audio_cache(ctx).await;
+
+    // Held from the limit checks to the enqueue, so that adds running at the same time cannot both
+    // pass the checks against the same queue.
+    let mut handler = handler_lock.lock().await;
+    let queued = handler.queue().current_queue();
+    let skipped = match apply_limits(ctx, msg, &queued, &mut resolved).await {
+        Ok(skipped) => skipped.unwrap_or_default(),
+        Err(reason) => {
+            drop(handler);
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let queued_before = handler.queue().len();
+    let count = resolved.sources.len();
+    let mut title = String::new();
+    for (source, metadata, track_source) in resolved.sources {
+        title = track_title(&metadata);
+        let mut info = TrackInfo::new(metadata, track_source, msg);
+        let source = info.apply_filter(source, filter);
+        enqueue_track(&mut handler, &cache, source, info, f32::from(volume) / 100.0).await;
+    }
+
+    let added = match resolved.name {
+        Some(name) => format!("{} tracks from **{}**", count, name),
+        None => format!("**{}**", title),
+    };
+
+    if play_next && queued_before > 0 {
+        handler.queue().modify_queue(|queue| {
+            let new = queue.split_off(queue.len() - count);
+            for (offset, queued) in new.into_iter().enumerate() {
+                queue.insert(1 + offset, queued);
+            }
+        });
+
+        msg.channel_id.say(&ctx.http, format!("Added {} to play next.{}", added, skipped)).await?;
+
+        return Ok(());
+    }
+
+    if settings.round_robin {
+        interleave_requesters(handler.queue(), count).await;
+    }
+
+    msg.channel_id.say(&ctx.http,
+        format!("Added {} to the queue. Songs in the queue: {}.{}",
+                added, handler.queue().len(), skipped)
+    ).await?;
+
+    Ok(())
+}
+
+async fn list_queue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let tracks = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.queue().current_queue(),
+        None => {
+            msg.channel_id.say(&ctx.http, "Not in voice channel.").await?;
+
+            return Ok(());
+        },
+    };
+
+    if tracks.is_empty() {
+        msg.channel_id.say(&ctx.http, "The queue is empty.").await?;
+
+        return Ok(());
+    }
+
+    let mut entries = Vec::with_capacity(tracks.len());
+    for track in &tracks {
+        entries.push(get_track_info(track).await);
+    }
+
+    let elapsed = track_position(&tracks[0], &entries[0]).await.as_secs();
+    let remaining = entries.iter()
+        .filter_map(|entry| entry.metadata.duration)
+        .map(|duration| duration.as_secs())
+        .sum::<u64>()
+        .saturating_sub(elapsed);
+
+    let pages = entries.len().div_ceil(QUEUE_PAGE_SIZE);
+    let mut page = args.single::<usize>().unwrap_or(1).clamp(1, pages) - 1;
+    let loop_mode = guild_loop_mode(ctx, guild_id).await;
+
+    let (embed, buttons) = queue_page(&entries, page, remaining, loop_mode);
+    let mut message = msg.channel_id.send_message(&ctx.http,
+        CreateMessage::new().embed(embed).components(buttons)
+    ).await?;
+
+    let mut interactions = message.await_component_interactions(&ctx.shard)
+        .timeout(Duration::from_secs(120))
+        .stream();
+
+    while let Some(interaction) = interactions.next().await {
+        match interaction.data.custom_id.as_str() {
+            "queue_prev" => page = page.saturating_sub(1),
+            "queue_next" => page = (page + 1).min(pages - 1),
+            _ => continue,
+        }
+
+        let (embed, buttons) = queue_page(&entries, page, remaining, loop_mode);
+        interaction.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new().embed(embed).components(buttons)
+        )).await?;
+    }
+
+    message.edit(&ctx.http, EditMessage::new().components(vec![])).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn about(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.say(&ctx.http, "Alfred. Poproś o co chesz, paniczu.").await?;
+
+    Ok(())
+}
+
+#[command]
+async fn am_i_admin(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    if is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, "Yes, you are.").await?;
+
+        return Ok(());
+    }
+
+    msg.channel_id.say(&ctx.http, "No, you are not..").await?;
+
+    Ok(())
+}
+
+#[command]
+async fn ping(ctx: &Context, msg: &Message) -> CommandResult {
+    if let Err(error) = msg.channel_id.say(&ctx.http, "Pong").await {
+        println!("Error sending message: {}.", error);
+    }
+    return Ok(());
+}
+
+// #[command]
+// async fn jd(ctx: &Context, msg: &Message) -> CommandResult {
+//     if let Err(error) = msg.channel_id.send_message(&ctx.http, |m: MessageBuilder| {
+//         m.("JD!").add_file(Path::new("media/dis.png"))
+//     }).await {
+//         println!("Error sending message: {:?}.", error);
+//     }
+//
+//     Ok(())
+// }
+
+#[command("cache")]
+#[sub_commands(cache_purge)]
+#[usage = "| purge <url|all>"]
+#[description = "Shows how full the audio cache is and how often it is used."]
+async fn cache_stats(ctx: &Context, msg: &Message) -> CommandResult {
+    let cache = audio_cache(ctx).await;
+    let cache = cache.read().await;
+
+    if !cache.enabled() {
+        msg.channel_id.say(&ctx.http, "The audio cache is turned off.").await?;
+        return Ok(());
+    }
+
+    let requests = cache.hits + cache.misses;
+    let hit_rate = if requests == 0 { 0.0 } else { cache.hits as f64 * 100.0 / requests as f64 };
+
+    let mut recent = String::new();
+    for (key, entry) in cache.entries().iter().take(5) {
+        let _ = writeln!(recent, "**{}** `{}` <t:{}:R>\n{}",
+            track_title(&entry.track.metadata()), utils::to_size(entry.size), entry.last_used, key);
+    }
+
+    let embed = CreateEmbed::new()
+        .title("Audio cache")
+        .field("Tracks", cache.len().to_string(), true)
+        .field("Size", format!("{} / {}", utils::to_size(cache.size()), utils::to_size(cache.budget())), true)
+        .field("Hit rate", format!("{:.0}% ({} of {} plays)", hit_rate, cache.hits, requests), true)
+        .field("Recently used", if recent.is_empty() { String::from("Nothing cached yet.") } else { recent }, false)
+        .footer(CreateEmbedFooter::new("Play counts are since the last restart"));
+    msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+#[command("purge")]
+#[usage = "<url|all>"]
+#[description = "Deletes one track or everything from the audio cache."]
+async fn cache_purge(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let target = args.rest().trim();
+    if target.is_empty() {
+        msg.channel_id.say(&ctx.http, ":warning: Use command like this: cache purge <url|all>").await?;
+        return Ok(());
+    }
+
+    let cache = audio_cache(ctx).await;
+    let mut cache = cache.write().await;
+    let reply = if target == "all" {
+        let count = cache.len();
+        let freed = cache.purge();
+        format!("Purged {} from the cache, freeing {}.", count_tracks(count), utils::to_size(freed))
+    } else {
+        match canonical_url(target).and_then(|key| cache.remove(&key)) {
+            Some(entry) => format!("Purged **{}** from the cache, freeing {}.",
+                track_title(&entry.track.metadata()), utils::to_size(entry.size)),
+            None => String::from(":warning: That URL is not cached."),
+        }
+    };
+    if let Err(error) = cache.save().await {
+        println!("Failed to save the audio cache index: {}", error);
+    }
+
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn create_channel(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(ctx,"Not yet implemented.").await?;
+    !unimplemented!()
+}
+
+// #[command]
+// async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
+//     let data = ctx.data.read().await;
+//     let shard_manager = match data.get::<ShardManagerContainer>() {
+//         Some(v) => v,
+//         None => {
+//             msg.reply(ctx, "There was a problem getting the shard manager. (Nie przejmuj się tym.)").await?;
+//
+//             return Ok(());
+//         },
+//     };
+//
+//     let manger = shard_manager.lock().await;
+//     let runners = manger.runners.lock().await;
+//
+//     let runner = match runners.get(&ShardId(ctx.shard_id)) {
+//         Some(runner) => runner,
+//         None => {
+//             msg.reply(ctx, "No shard found.").await?;
+//
+//             return Ok(());
+//         },
+//     };
+//
+//     msg.reply(ctx, &format!("The shard latency is {:?}", runner.latency)).await?;
+//
+//     Ok(())
+// }
+
+#[command]
+async fn przepros(ctx:  &Context, msg: &Message) -> CommandResult {
+    msg.reply(ctx, "Przepraszam, mój panie.").await?;
+
+    Ok(())
+}
+
+#[command]
+#[usage = "<url|query> [shuffle], or attach an audio file"]
+async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let url = args.single::<String>().ok();
+    let shuffle = args.single::<String>().is_ok_and(|arg| arg == "shuffle");
+    let start = url.as_deref().and_then(youtube::start_time);
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let resolved = match url {
+        Some(url) => resolve_sources(ctx, url, shuffle).await,
+        None => resolve_attachments(ctx, msg).await,
+    };
+    let mut resolved = match resolved {
+        Ok(resolved) if !resolved.sources.is_empty() => resolved,
+        Ok(_) => {
+            msg.channel_id.say(&ctx.http, ":warning: Nothing to play there.").await?;
+            return Ok(());
+        },
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    // `play` replaces the queue, so the limits start from an empty one.
+    let skipped = match apply_limits(ctx, msg, &[], &mut resolved).await {
+        Ok(skipped) => skipped.unwrap_or_default(),
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let volume = guild_settings(ctx, msg.guild_id.unwrap()).await.volume;
+    let filter = guild_filter(ctx, msg.guild_id.unwrap()).await;
+    let cache = audio_cache(ctx).await;
+
+    let mut handler = handler_lock.lock().await;
+
+    handler.stop();
+    handler.queue().stop();
+    let title = track_title(&resolved.sources[0].1);
+    let count = resolved.sources.len();
+    for (source, metadata, track_source) in resolved.sources {
+        let mut info = TrackInfo::new(metadata, track_source, msg);
+        let linked_start = start.filter(|_| resolved.name.is_none());
+        if let (Some(start), true) = (linked_start, filter != Filter::Off) {
+            info.start = start;
+        }
+        let source = info.apply_filter(source, filter);
+        let track = enqueue_track(&mut handler, &cache, source, info, f32::from(volume) / 100.0).await;
+
+        // Songbird holds the seek until the track is ready, so it starts at the linked offset.
+        if let (Some(start), Filter::Off) = (linked_start, filter) {
+            let _ = track.seek(start);
+        }
+    }
+
+    let reply = match resolved.name {
+        Some(name) => format!("Playing **{}**. Added {} tracks from **{}**.", title, count, name),
+        None => format!("Playing **{}**.", title),
+    };
+    msg.channel_id.say(&ctx.http, format!("{}{}", reply, skipped)).await?;
+
+    handler.queue().resume().expect("Couldn't resume queue.");
+
+    Ok(())
+}
+
+#[command]
+#[checks(DJ)]
+async fn stop(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let reply = match stop_playback(ctx, msg.guild_id.unwrap()).await {
+        Ok(reply) | Err(reply) => reply,
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+/// Stops the current track and clears the queue. `Err` holds a message for the user.
+async fn stop_playback(ctx: &Context, guild_id: GuildId) -> Result<String, String> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let handler_lock = manager.get(guild_id).ok_or_else(|| String::from("Not in voice channel."))?;
+    let mut handler = handler_lock.lock().await;
+    handler.queue().stop();
+    handler.stop();
+
+    Ok(String::from("Playback stopped, queue cleared."))
+}
+
+#[command]
+#[description = "Votes to skip the current track. Its requester and DJs skip it right away."]
+async fn skip(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    if let Some(reply) = skip_current(ctx, msg.guild_id.unwrap(), msg.author.id, member_roles(msg)).await {
+        msg.channel_id.say(&ctx.http, reply).await?;
+    }
+
+    Ok(())
+}
+
+/// Skips the current track for its requester or a DJ, and counts a vote for anyone else.
+/// Returns the reply, if there is anything to say.
+async fn skip_current(ctx: &Context, guild_id: GuildId, user_id: UserId, roles: &[RoleId]) -> Option<String> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let handler_lock = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock,
+        None => return Some(String::from("Not in a voice channel to play in.")),
+    };
+
+    let (channel, current) = {
+        let handler = handler_lock.lock().await;
+        (handler.current_channel(), handler.queue().current())
+    };
+    let current = match current {
+        Some(current) => current,
+        None => return Some(String::from("Nothing to skip.")),
+    };
+
+    let info = get_track_info(&current).await;
+    if info.requester != Some(user_id) && !has_dj_role(ctx, Some(guild_id), roles).await {
+        let listeners = channel.map_or(Vec::new(), |channel| listeners(ctx, guild_id, channel));
+        if !listeners.contains(&user_id) {
+            return Some(String::from(":warning: Join my voice channel to vote."));
+        }
+
+        let fraction = {
+            let data = ctx.data.read().await;
+            data.get::<ConfigKey>()
+                .expect("Config placed in at init.")
+                .skip_fraction()
+        };
+        let required = ((listeners.len() as f64 * fraction).ceil() as usize).max(1);
+
+        let votes = {
+            let states = guild_states(ctx).await;
+            let mut states = states.write().await;
+            let state = states.entry(guild_id).or_default();
+
+            if state.vote_track.as_ref().is_none_or(|track| track.uuid() != current.uuid()) {
+                state.vote_track = Some(current.clone());
+                state.skip_votes.clear();
+            }
+            state.skip_votes.insert(user_id);
+            state.skip_votes.retain(|user| listeners.contains(user));
+
+            state.skip_votes.len()
+        };
+
+        if votes < required {
+            return Some(format!("Voted to skip **{}**: {}/{} votes.", track_title(&info.metadata), votes, required));
+        }
+    }
+
+    let next = {
+        let handler = handler_lock.lock().await;
+        let queue = handler.queue();
+        let tracks = queue.current_queue();
+        // The track may have ended while the votes were counted.
+        if tracks.first().is_none_or(|track| track.uuid() != current.uuid()) {
+            return None;
+        }
+        let _ = queue.skip();
+
+        tracks.get(1).cloned()
+    };
+
+    let skipped = track_title(&info.metadata);
+    match next {
+        Some(next) => {
+            let next = track_title(&get_track_info(&next).await.metadata);
+            Some(format!("Skipped **{}**. Now playing: **{}**.", skipped, next))
+        },
+        None => Some(format!("Skipped **{}**. No more songs in a queue.", skipped)),
+    }
+}
+
+#[command]
+#[usage = "<url> [shuffle] | list [page]"]
+async fn queue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let url = match args.single::<String>() {
+        Ok(url) => url,
+        Err(_) => {
+            msg.channel_id.say(&ctx.http, "Must provide url for audio or video, or use `queue list [page]`.")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    if url == "list" {
+        return list_queue(ctx, msg, args).await;
+    }
+
+    if !url.starts_with("http") && !url.starts_with("spotify:") {
+        msg.channel_id.say(&ctx.http, "Must provide a valid URL.").await;
+
+        return Ok(());
+    }
+
+    let shuffle = args.single::<String>().is_ok_and(|arg| arg == "shuffle");
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    match resolve_sources(ctx, url, shuffle).await {
+        Ok(resolved) => add_to_queue(ctx, msg, handler_lock, resolved, false).await?,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+        },
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Adds audio attachments of this message, or of the message it replies to, to the queue."]
+async fn playattachment(ctx: &Context, msg: &Message) -> CommandResult {
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    match resolve_attachments(ctx, msg).await {
+        Ok(resolved) => add_to_queue(ctx, msg, handler_lock, resolved, false).await?,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+        },
+    }
+
+    Ok(())
+}
+
+#[command]
+#[checks(DJ)]
+async fn reset_queue(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let handler = handler_lock.lock().await;
+        let queue = handler.queue();
+        queue.stop();
+
+        msg.channel_id.say(&ctx.http, "Queue cleared.").await;
+    } else {
+        msg.channel_id.say(&ctx.http, "Not in voice channel.").await;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("np")]
+#[description = "Shows the current track with buttons to control playback."]
+async fn nowplaying(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    let track = match current_track(ctx, guild_id).await {
+        Ok(track) => track,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    // The controller moves down to the newest message, the old one keeps its embed without buttons.
+    finish_now_playing(ctx, guild_id).await;
+
+    let (embed, buttons) = now_playing_message(ctx, guild_id, &track).await;
+    let message = msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed).components(buttons)).await?;
+    guild_states(ctx).await
+        .write().await
+        .entry(guild_id)
+        .or_default()
+        .now_playing = Some((msg.channel_id, message.id));
+
+    Ok(())
+}
+
+/// The embed and buttons of the now-playing controller for `track`.
+async fn now_playing_message(ctx: &Context, guild_id: GuildId, track: &TrackHandle) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let paused = track.get_info().await.is_ok_and(|state| state.playing == PlayMode::Pause);
+    let loop_mode = guild_loop_mode(ctx, guild_id).await;
+
+    let info = get_track_info(track).await;
+    let elapsed = track_position(track, &info).await.as_secs();
+    let metadata = info.metadata;
+
+    let progress = match metadata.duration {
+        Some(duration) => format!("{} `{} / {}`",
+            utils::progress_bar(elapsed, duration.as_secs(), 20),
+            utils::to_time(elapsed),
+            utils::to_time(duration.as_secs())),
+        None => format!("`{}` (live)", utils::to_time(elapsed)),
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title(track_title(&metadata))
+        .description(progress);
+
+    if let Some(url) = &metadata.source_url {
+        embed = embed.url(url);
+    }
+    if let Some(thumbnail) = &metadata.thumbnail {
+        embed = embed.thumbnail(thumbnail);
+    }
+    if let Some(artist) = &metadata.artist {
+        embed = embed.field("Artist", artist, true);
+    }
+    if let Some(channel) = &metadata.channel {
+        embed = embed.field("Channel", channel, true);
+    }
+    let status = if paused { "Paused" } else { "Now playing" };
+    let volume = guild_settings(ctx, guild_id).await.volume;
+    let mut footer = format!("{} | Loop: {} | Volume: {}%", status, loop_mode, volume);
+    if info.filter != Filter::Off {
+        let _ = write!(footer, " | Filter: {}", info.filter);
+    }
+    embed = embed.footer(CreateEmbedFooter::new(footer));
+
+    let pause = if paused {
+        CreateButton::new("np_pause").label("Resume")
+    } else {
+        CreateButton::new("np_pause").label("Pause").style(ButtonStyle::Secondary)
+    };
+    let buttons = vec![
+        CreateActionRow::Buttons(vec![
+            pause,
+            CreateButton::new("np_skip").label("Skip").style(ButtonStyle::Secondary),
+            CreateButton::new("np_stop").label("Stop").style(ButtonStyle::Danger),
+            CreateButton::new("np_loop").label(format!("Loop: {}", loop_mode)).style(ButtonStyle::Secondary),
+            CreateButton::new("np_shuffle").label("Shuffle").style(ButtonStyle::Secondary),
+        ]),
+        CreateActionRow::Buttons(vec![
+            CreateButton::new("np_voldown").label(format!("Volume -{}", VOLUME_STEP)).style(ButtonStyle::Secondary),
+            CreateButton::new("np_volup").label(format!("Volume +{}", VOLUME_STEP)).style(ButtonStyle::Secondary),
+        ]),
+    ];
+
+    (embed, buttons)
+}
+
+/// Shows the current track on the guild's controller, editing it in place or posting a new one in
+/// the guild's text channel. Once nothing is playing the controller loses its buttons.
+async fn refresh_now_playing(ctx: &Context, guild_id: GuildId) {
+    let track = match current_track(ctx, guild_id).await {
+        Ok(track) => track,
+        Err(_) => {
+            finish_now_playing(ctx, guild_id).await;
+            return;
+        },
+    };
+
+    let (text_channel, controller) = match guild_states(ctx).await.read().await.get(&guild_id) {
+        Some(state) => (state.text_channel, state.now_playing),
+        None => (None, None),
+    };
+    let (embed, buttons) = now_playing_message(ctx, guild_id, &track).await;
+
+    if let Some((channel_id, message_id)) = controller {
+        let edit = EditMessage::new().embed(embed.clone()).components(buttons.clone());
+        if channel_id.edit_message(&ctx.http, message_id, edit).await.is_ok() {
+            return;
+        }
+    }
+
+    // The controller was deleted, or there was none yet.
+    let channel_id = match text_channel {
+        Some(channel_id) => channel_id,
+        None => return,
+    };
+    match channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed).components(buttons)).await {
+        Ok(message) => {
+            guild_states(ctx).await
+                .write().await
+                .entry(guild_id)
+                .or_default()
+                .now_playing = Some((channel_id, message.id));
+        },
+        Err(error) => println!("Failed to post the now-playing controller in {}: {:?}", guild_id, error),
+    }
+}
+
+/// Strips the buttons off the guild's controller, leaving the last track it showed.
+async fn finish_now_playing(ctx: &Context, guild_id: GuildId) {
+    let controller = guild_states(ctx).await
+        .write().await
+        .get_mut(&guild_id)
+        .and_then(|state| state.now_playing.take());
+
+    if let Some((channel_id, message_id)) = controller {
+        let _ = channel_id.edit_message(&ctx.http, message_id, EditMessage::new().components(vec![])).await;
+    }
+}
+
+/// Keeps the now-playing controller in step with tracks starting, pausing and ending.
+struct NowPlayingUpdater {
+    ctx: Context,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for NowPlayingUpdater {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        refresh_now_playing(&self.ctx, self.guild_id).await;
+        None
+    }
+}
+
+/// Handles a click on the now-playing controller, with the same checks as the matching commands.
+pub(crate) async fn handle_now_playing_button(ctx: &Context, interaction: &ComponentInteraction) {
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    let user_id = interaction.user.id;
+    let roles = interaction.member.as_ref().map_or(&[][..], |member| member.roles.as_slice());
+
+    let reply = match interaction.data.custom_id.as_str() {
+        "np_pause" => match current_track(ctx, guild_id).await {
+            Ok(track) => {
+                let paused = track.get_info().await.is_ok_and(|state| state.playing == PlayMode::Pause);
+                let toggled = if paused { track.play() } else { track.pause() };
+                toggled.err().map(|_| String::from(":warning: Could not pause or resume the track."))
+            },
+            Err(reason) => Some(reason),
+        },
+        "np_skip" => skip_current(ctx, guild_id, user_id, roles).await,
+        "np_stop" | "np_shuffle" if !roles_dj_allowed(ctx, Some(guild_id), roles).await => {
+            Some(String::from(":warning: This command needs the DJ role."))
+        },
+        "np_stop" => stop_playback(ctx, guild_id).await.err(),
+        "np_loop" => {
+            let loop_mode = guild_loop_mode(ctx, guild_id).await.next();
+            set_loop_mode(ctx, guild_id, loop_mode).await;
+            None
+        },
+        "np_shuffle" => Some(match shuffle_upcoming(ctx, guild_id).await {
+            Ok(reply) | Err(reply) => reply,
+        }),
+        "np_voldown" | "np_volup" if !roles_dj_allowed(ctx, Some(guild_id), roles).await => {
+            Some(String::from(":warning: Changing the volume needs the DJ role."))
+        },
+        "np_voldown" | "np_volup" => {
+            let settings = guild_settings(ctx, guild_id).await;
+            let volume = if interaction.data.custom_id == "np_volup" {
+                settings.volume.saturating_add(VOLUME_STEP).min(settings.max_volume)
+            } else {
+                settings.volume.saturating_sub(VOLUME_STEP)
+            };
+
+            if volume == settings.volume && interaction.data.custom_id == "np_volup" {
+                Some(format!(":warning: Volume on this server is limited to {}%.", settings.max_volume))
+            } else {
+                update_guild_settings(ctx, guild_id, |settings| settings.volume = volume).await;
+                apply_volume(ctx, guild_id, volume).await;
+                None
+            }
+        },
+        _ => return,
+    };
+
+    let response = match reply {
+        Some(reply) => interaction.create_response(&ctx.http, CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new().content(reply).ephemeral(true)
+        )).await,
+        None => interaction.defer(&ctx.http).await,
+    };
+    if let Err(error) = response {
+        println!("Failed to answer a now-playing button in {}: {:?}", guild_id, error);
+    }
+
+    refresh_now_playing(ctx, guild_id).await;
+    snapshot_guild(ctx, guild_id).await;
+}
+
+#[command]
+async fn pause(ctx: &Context, msg: &Message) -> CommandResult {
+    let track = match current_track(ctx, msg.guild_id.unwrap()).await {
+        Ok(track) => track,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    if track.get_info().await.is_ok_and(|state| state.playing == PlayMode::Pause) {
+        msg.channel_id.say(&ctx.http, "Already paused.").await?;
+        return Ok(());
+    }
+
+    let title = track_title(&get_track_info(&track).await.metadata);
+    match track.pause() {
+        Ok(()) => msg.channel_id.say(&ctx.http, format!("Paused **{}**.", title)).await?,
+        Err(_) => msg.channel_id.say(&ctx.http, ":warning: Could not pause the track.").await?,
+    };
+
+    Ok(())
+}
+
+#[command]
+async fn resume(ctx: &Context, msg: &Message) -> CommandResult {
+    let track = match current_track(ctx, msg.guild_id.unwrap()).await {
+        Ok(track) => track,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    if track.get_info().await.is_ok_and(|state| state.playing == PlayMode::Play) {
+        msg.channel_id.say(&ctx.http, "Already playing.").await?;
+        return Ok(());
+    }
+
+    let title = track_title(&get_track_info(&track).await.metadata);
+    match track.play() {
+        Ok(()) => msg.channel_id.say(&ctx.http, format!("Resumed **{}**.", title)).await?,
+        Err(_) => msg.channel_id.say(&ctx.http, ":warning: Could not resume the track.").await?,
+    };
+
+    Ok(())
+}
+
+/// How the position given to `seek` is read.
+enum Seek {
+    Absolute,
+    Forward,
+    Back,
+}
+
+#[command]
+#[usage = "<[+|-]seconds|mm:ss|hh:mm:ss>"]
+#[example = "1:30"]
+#[example = "+30"]
+async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let position = match args.single::<String>() {
+        Ok(position) => position,
+        Err(_) => {
+            msg.channel_id.say(&ctx.http, "Must provide a position, e.g. `1:30`, `+30` or `-15`.").await?;
+            return Ok(());
+        },
+    };
+
+    let (offset, direction) = match position.strip_prefix('+') {
+        Some(offset) => (offset, Seek::Forward),
+        None => match position.strip_prefix('-') {
+            Some(offset) => (offset, Seek::Back),
+            None => (position.as_str(), Seek::Absolute),
+        },
+    };
+    let offset = match utils::parse_time(offset) {
+        Some(offset) => offset,
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: `{}` is not a valid position.", position)).await?;
+            return Ok(());
+        },
+    };
+
+    let track = match current_track(ctx, msg.guild_id.unwrap()).await {
+        Ok(track) => track,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let info = get_track_info(&track).await;
+    let elapsed = track_position(&track, &info).await.as_secs();
+    let target = match direction {
+        Seek::Absolute => offset,
+        Seek::Forward => elapsed.saturating_add(offset),
+        Seek::Back => elapsed.saturating_sub(offset),
+    };
+
+    if info.metadata.duration.is_some_and(|duration| target >= duration.as_secs()) {
+        msg.channel_id.say(&ctx.http, ":warning: That is past the end of the track.").await?;
+        return Ok(());
+    }
+
+    // ffmpeg output cannot seek, so filtered tracks are restarted at the target instead.
+    if info.filter != Filter::Off || !info.start.is_zero() {
+        match restart_current(ctx, msg.guild_id.unwrap(), Duration::from_secs(target)).await {
+            Ok(()) => msg.channel_id.say(&ctx.http, format!("Seeked to `{}`.", utils::to_time(target))).await?,
+            Err(reason) => msg.channel_id.say(&ctx.http, reason).await?,
+        };
+
+        return Ok(());
+    }
+
+    match track.seek_async(Duration::from_secs(target)).await {
+        Ok(position) => {
+            msg.channel_id.say(&ctx.http, format!("Seeked to `{}`.", utils::to_time(position.as_secs()))).await?;
+        },
+        Err(error) => {
+            println!("Failed to seek to {}s: {:?}", target, error);
+            msg.channel_id.say(&ctx.http, ":warning: Could not seek in this track.").await?;
+        },
+    }
+
+    Ok(())
+}
+
+/// Sets every queued track to `volume` percent.
+async fn apply_volume(ctx: &Context, guild_id: GuildId, volume: u16) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let handler = handler_lock.lock().await;
+        for track in handler.queue().current_queue() {
+            let _ = track.set_volume(f32::from(volume) / 100.0);
+        }
+    }
+}
+
+#[command]
+#[sub_commands(volume_max)]
+#[usage = "[0-200] | max <0-200>"]
+#[description = "Shows or sets the playback volume of this server."]
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let settings = guild_settings(ctx, guild_id).await;
+
+    if args.is_empty() {
+        msg.channel_id.say(&ctx.http,
+            format!("Volume is at {}% (max {}%).", settings.volume, settings.max_volume)
+        ).await?;
+
+        return Ok(());
+    }
+
+    if !dj_allowed(ctx, msg).await {
+        msg.channel_id.say(&ctx.http, ":warning: Changing the volume needs the DJ role.").await?;
+        return Ok(());
+    }
+
+    let volume = match args.single::<u16>() {
+        Ok(volume) if volume <= 200 => volume,
+        _ => {
+            msg.channel_id.say(&ctx.http, ":warning: Volume must be a number from 0 to 200.").await?;
+            return Ok(());
+        },
+    };
+
+    if volume > settings.max_volume {
+        msg.channel_id.say(&ctx.http,
+            format!(":warning: Volume on this server is limited to {}%.", settings.max_volume)
+        ).await?;
+
+        return Ok(());
+    }
+
+    update_guild_settings(ctx, guild_id, |settings| settings.volume = volume).await;
+    apply_volume(ctx, guild_id, volume).await;
+
+    msg.channel_id.say(&ctx.http, format!("Volume set to {}%.", volume)).await?;
+
+    Ok(())
+}
+
+#[command("max")]
+#[usage = "<0-200>"]
+#[description = "Sets the highest volume members can choose. Admins only."]
+async fn volume_max(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can change the volume limit.").await?;
+        return Ok(());
+    }
+
+    let max_volume = match args.single::<u16>() {
+        Ok(max_volume) if max_volume <= 200 => max_volume,
+        _ => {
+            msg.channel_id.say(&ctx.http, ":warning: The limit must be a number from 0 to 200.").await?;
+            return Ok(());
+        },
+    };
+
+    let guild_id = msg.guild_id.unwrap();
+    let settings = update_guild_settings(ctx, guild_id, |settings| {
+        settings.max_volume = max_volume;
+        settings.volume = settings.volume.min(max_volume);
+    }).await;
+    apply_volume(ctx, guild_id, settings.volume).await;
+
+    msg.channel_id.say(&ctx.http,
+        format!("Volume limit set to {}%. Volume is at {}%.", max_volume, settings.volume)
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[checks(DJ)]
+#[description = "Shuffles the upcoming tracks."]
+async fn shuffle(ctx: &Context, msg: &Message) -> CommandResult {
+    let reply = match shuffle_upcoming(ctx, msg.guild_id.unwrap()).await {
+        Ok(reply) | Err(reply) => reply,
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+/// Shuffles everything after the current track. `Err` holds a message for the user.
+async fn shuffle_upcoming(ctx: &Context, guild_id: GuildId) -> Result<String, String> {
+    let queue = guild_queue(ctx, guild_id).await?;
+
+    if queue.len() < 3 {
+        return Err(String::from("Not enough songs in the queue to shuffle."));
+    }
+
+    queue.modify_queue(|queue| {
+        queue.make_contiguous()[1..].shuffle(&mut rand::thread_rng());
+    });
+
+    Ok(format!("Shuffled {} upcoming tracks.", queue.len() - 1))
+}
+
+#[command]
+#[usage = "<position|from-to>"]
+#[example = "3"]
+#[example = "2-5"]
+#[description = "Removes tracks from the queue. Only DJs can remove tracks requested by others."]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (start, end) = match args.single::<String>().ok().as_deref().and_then(parse_positions) {
+        Some(range) => range,
+        None => {
+            msg.channel_id.say(&ctx.http, "Must provide a position or a range, e.g. `3` or `2-5`.").await?;
+            return Ok(());
+        },
+    };
+
+    let queue = match guild_queue(ctx, msg.guild_id.unwrap()).await {
+        Ok(queue) => queue,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let removed = match check_positions(&queue, start, end) {
+        Ok(()) => remove_positions(ctx, msg, &queue, start, end).await,
+        Err(reason) => Err(reason),
+    };
+    let removed = match removed {
+        Ok(removed) => removed,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let reply = match removed.as_slice() {
+        [entry] => format!("Removed **{}** from the queue.", track_title(&entry.metadata)),
+        _ => format!("Removed {} tracks from the queue.", removed.len()),
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("move")]
+#[checks(DJ)]
+#[usage = "<from> <to>"]
+#[description = "Moves an upcoming track to another position in the queue."]
+async fn move_track(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (from, to) = match (args.single::<usize>(), args.single::<usize>()) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => {
+            msg.channel_id.say(&ctx.http, "Must provide two positions, e.g. `move 5 2`.").await?;
+            return Ok(());
+        },
+    };
+
+    let queue = match guild_queue(ctx, msg.guild_id.unwrap()).await {
+        Ok(queue) => queue,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    if let Err(reason) = check_positions(&queue, from.min(to), from.max(to)) {
+        msg.channel_id.say(&ctx.http, reason).await?;
+        return Ok(());
+    }
+
+    let moved = queue.modify_queue(|queue| {
+        // The current track may have ended since the positions were checked.
+        if from.max(to) > queue.len() {
+            return None;
+        }
+        let queued = queue.remove(from - 1)?;
+        let handle = queued.handle();
+        queue.insert(to - 1, queued);
+        Some(handle)
+    });
+
+    match moved {
+        Some(track) => {
+            let title = track_title(&get_track_info(&track).await.metadata);
+            msg.channel_id.say(&ctx.http, format!("Moved **{}** to position {}.", title, to)).await?;
+        },
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: There are only {} tracks in the queue.", queue.len())).await?;
+        },
+    }
+
+    Ok(())
+}
+
+#[command]
+#[usage = "<url|query> [shuffle]"]
+#[description = "Adds tracks right after the current one."]
+async fn playnext(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let url = match args.single::<String>() {
+        Ok(url) => url,
+        Err(_) => {
+            msg.channel_id.say(&ctx.http, "Must provide a url or a search query.").await?;
+            return Ok(());
+        },
+    };
+    let shuffle = args.single::<String>().is_ok_and(|arg| arg == "shuffle");
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    match resolve_sources(ctx, url, shuffle).await {
+        Ok(resolved) => add_to_queue(ctx, msg, handler_lock, resolved, true).await?,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+        },
+    }
+
+    Ok(())
+}
+
+#[command]
+#[usage = "<position>"]
+#[description = "Skips to a position in the queue, dropping the tracks before it. Only the requester of the current track and DJs can skip past other tracks."]
+async fn skipto(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let position = match args.single::<usize>() {
+        Ok(position) => position,
+        Err(_) => {
+            msg.channel_id.say(&ctx.http, "Must provide a queue position.").await?;
+            return Ok(());
+        },
+    };
+
+    let queue = match guild_queue(ctx, msg.guild_id.unwrap()).await {
+        Ok(queue) => queue,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    if let Err(reason) = check_positions(&queue, position, position) {
+        msg.channel_id.say(&ctx.http, reason).await?;
+        return Ok(());
+    }
+
+    // Jumping over tracks is not something a vote decides, only skipping the current one is.
+    let requester = match queue.current() {
+        Some(current) => get_track_info(&current).await.requester,
+        None => None,
+    };
+    if position > 2 {
+        if requester != Some(msg.author.id) && !is_dj(ctx, msg).await {
+            msg.channel_id.say(&ctx.http,
+                ":warning: Only the requester of the current track or a DJ can skip past other tracks. Use `skip` to vote."
+            ).await?;
+            return Ok(());
+        }
+
+        if let Err(reason) = remove_positions(ctx, msg, &queue, 2, position - 1).await {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        }
+    }
+
+    if let Some(reply) = skip_current(ctx, msg.guild_id.unwrap(), msg.author.id, member_roles(msg)).await {
+        msg.channel_id.say(&ctx.http, reply).await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[usage = "<query>"]
+#[description = "Searches YouTube and lets you pick which result to queue."]
+async fn search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let query = args.rest().trim().to_string();
+    if query.is_empty() {
+        msg.channel_id.say(&ctx.http, "Must provide a search query.").await?;
+        return Ok(());
+    }
+
+    let http_client = get_http_client(ctx).await;
+    let results = match YoutubeDl::new_search(http_client, query.clone()).search(Some(SEARCH_RESULTS)).await {
+        Ok(results) => results.into_iter()
+            .filter(|result| result.source_url.is_some())
+            .collect::<Vec<_>>(),
+        Err(error) => {
+            println!("Failed to search for {}: {:?}", query, error);
+            msg.channel_id.say(&ctx.http, ":warning: Search failed, try again later.").await?;
+            return Ok(());
+        },
+    };
+
+    if results.is_empty() {
+        msg.channel_id.say(&ctx.http, format!("No results for **{}**.", query)).await?;
+        return Ok(());
+    }
+
+    let mut description = String::new();
+    let mut options = Vec::with_capacity(results.len());
+    for (index, result) in results.iter().enumerate() {
+        let duration = result.duration
+            .map_or(String::from("live"), |duration| utils::to_time(duration.as_secs()));
+        let channel = result.channel.as_deref().unwrap_or("unknown");
+        let title = track_title(result);
+
+        let _ = writeln!(description, "`{}.` **{}** - {} `{}`", index + 1, title, channel, duration);
+        // Discord caps select menu labels and descriptions at 100 characters.
+        let label = format!("{}. {}", index + 1, title).chars().take(100).collect::<String>();
+        let option = CreateSelectMenuOption::new(label, index.to_string())
+            .description(channel.chars().take(100).collect::<String>());
+        options.push(option);
+    }
+
+    let embed = CreateEmbed::new()
+        .title(format!("Results for \"{}\"", query))
+        .description(description)
+        .footer(CreateEmbedFooter::new("Pick a track within 60 seconds"));
+    let menu = CreateSelectMenu::new("search_pick", CreateSelectMenuKind::String { options })
+        .placeholder("Choose a track to queue");
+
+    let mut message = msg.channel_id.send_message(&ctx.http,
+        CreateMessage::new().embed(embed).components(vec![CreateActionRow::SelectMenu(menu)])
+    ).await?;
+
+    let mut interactions = message.await_component_interactions(&ctx.shard)
+        .timeout(Duration::from_secs(60))
+        .stream();
+
+    let mut picked = None;
+    while let Some(interaction) = interactions.next().await {
+        if interaction.user.id != msg.author.id {
+            interaction.create_response(&ctx.http, CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("Only the person who searched can pick a result.")
+                    .ephemeral(true)
+            )).await?;
+            continue;
+        }
+
+        let index = match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                values.first().and_then(|value| value.parse::<usize>().ok())
+            },
+            _ => None,
+        };
+        let result = match index.and_then(|index| results.get(index)) {
+            Some(result) => result.clone(),
+            None => continue,
+        };
+
+        interaction.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(CreateEmbed::new().description(format!("Picked **{}**.", track_title(&result))))
+                .components(vec![])
+        )).await?;
+        picked = Some(result);
+        break;
+    }
+
+    let metadata = match picked {
+        Some(metadata) => metadata,
+        None => {
+            message.edit(&ctx.http, EditMessage::new()
+                .embed(CreateEmbed::new().description("Search timed out."))
+                .components(vec![])
+            ).await?;
+            return Ok(());
+        },
+    };
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let source = TrackSource::Url(metadata.source_url.clone().expect("Results without a URL are filtered out."));
+    let resolved = ResolvedSources {
+        name: None,
+        sources: vec![(source.to_input(get_http_client(ctx).await), metadata, source)],
+    };
+    add_to_queue(ctx, msg, handler_lock, resolved, false).await
+}
+
+#[command("djrole")]
+#[usage = "[@role|none]"]
+#[description = "Shows or sets the role allowed to stop, clear and skip without a vote. Admins only."]
+async fn dj_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    if args.is_empty() {
+        let reply = match guild_settings(ctx, guild_id).await.dj_role {
+            Some(role) => format!("The DJ role is <@&{}>.", role),
+            None => String::from("No DJ role is set, everyone can manage playback."),
+        };
+        msg.channel_id.say(&ctx.http, reply).await?;
+
+        return Ok(());
+    }
+
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can change the DJ role.").await?;
+        return Ok(());
+    }
+
+    let arg = args.single::<String>().unwrap_or_default();
+    let role = if arg == "none" {
+        None
+    } else {
+        match serenity::utils::parse_role_mention(&arg).or_else(|| arg.parse::<u64>().ok().map(RoleId::new)) {
+            Some(role) => Some(role),
+            None => {
+                msg.channel_id.say(&ctx.http, ":warning: Mention a role, give its id, or use `none`.").await?;
+                return Ok(());
+            },
+        }
+    };
+
+    update_guild_settings(ctx, guild_id, |settings| settings.dj_role = role).await;
+
+    let reply = match role {
+        Some(role) => format!("DJ role set to <@&{}>.", role),
+        None => String::from("DJ role removed, everyone can manage playback."),
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[usage = "[length <mm:ss|off> | user <count|off> | queue <count|off> | roundrobin <on|off>]"]
+#[example = "length 10:00"]
+#[example = "user 5"]
+#[description = "Shows or sets the limits applied when tracks are queued. Admins only."]
+async fn limits(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    if args.is_empty() {
+        let settings = guild_settings(ctx, guild_id).await;
+        let limit = |limit: Option<String>| limit.unwrap_or(String::from("none"));
+
+        let embed = CreateEmbed::new()
+            .title("Queue limits")
+            .field("Track length", limit(settings.max_track_length.map(utils::to_time)), true)
+            .field("Tracks per person", limit(settings.max_user_tracks.map(|max| max.to_string())), true)
+            .field("Queue length", limit(settings.max_queue_length.map(|max| max.to_string())), true)
+            .field("Round-robin", if settings.round_robin { "on" } else { "off" }, true);
+        msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+        return Ok(());
+    }
+
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can change the queue limits.").await?;
+        return Ok(());
+    }
+
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let value = args.single::<String>().unwrap_or_default().to_lowercase();
+    let off = value == "off" || value == "none";
+
+    let reply = match name.as_str() {
+        "length" => match utils::parse_time(&value) {
+            Some(max) if max > 0 => {
+                update_guild_settings(ctx, guild_id, |settings| settings.max_track_length = Some(max)).await;
+                format!("Tracks longer than {} will be rejected.", utils::to_time(max))
+            },
+            _ if off => {
+                update_guild_settings(ctx, guild_id, |settings| settings.max_track_length = None).await;
+                String::from("Track length is no longer limited.")
+            },
+            _ => String::from(":warning: Give a length like `10:00`, or `off`."),
+        },
+        "user" | "queue" => match value.parse::<usize>() {
+            Ok(max) if max > 0 => {
+                update_guild_settings(ctx, guild_id, |settings| match name.as_str() {
+                    "user" => settings.max_user_tracks = Some(max),
+                    _ => settings.max_queue_length = Some(max),
+                }).await;
+                format!("The {} limit is now {}.", if name == "user" { "per-person" } else { "queue" }, count_tracks(max))
+            },
+            _ if off => {
+                update_guild_settings(ctx, guild_id, |settings| match name.as_str() {
+                    "user" => settings.max_user_tracks = None,
+                    _ => settings.max_queue_length = None,
+                }).await;
+                format!("The {} limit is off.", if name == "user" { "per-person" } else { "queue" })
+            },
+            _ => String::from(":warning: Give a number of tracks, or `off`."),
+        },
+        "roundrobin" => match value.as_str() {
+            "on" | "off" => {
+                let round_robin = value == "on";
+                update_guild_settings(ctx, guild_id, |settings| settings.round_robin = round_robin).await;
+                format!("Round-robin ordering is {}.", value)
+            },
+            _ => String::from(":warning: Round-robin is either `on` or `off`."),
+        },
+        _ => String::from(":warning: Unknown limit, use `length`, `user`, `queue` or `roundrobin`."),
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[usage = "[off|bassboost|nightcore|vaporwave|karaoke|8d|speed <0.5-2.0>|pitch <0.5-2.0>]"]
+#[example = "nightcore"]
+#[example = "speed 1.5"]
+#[description = "Shows or sets the audio filter for the current and following tracks."]
+async fn filter(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    if args.is_empty() {
+        let filter = guild_filter(ctx, guild_id).await;
+        msg.channel_id.say(&ctx.http, format!("The filter is **{}**.", filter)).await?;
+
+        return Ok(());
+    }
+
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let factor = args.single::<String>().ok();
+    let filter = match Filter::parse(&name, factor.as_deref()) {
+        Some(filter) => filter,
+        None => {
+            msg.channel_id.say(&ctx.http,
+                ":warning: Unknown filter. Use `bassboost`, `nightcore`, `vaporwave`, `karaoke`, `8d`, \
+                `speed <0.5-2.0>`, `pitch <0.5-2.0>` or `off`."
+            ).await?;
+            return Ok(());
+        },
+    };
+
+    guild_states(ctx).await
+        .write().await
+        .entry(guild_id)
+        .or_default()
+        .filter = filter;
+
+    refilter_queue(ctx, guild_id, filter).await;
+
+    // Restart the current track where it is, so the change is heard right away.
+    if let Ok(track) = current_track(ctx, guild_id).await {
+        let info = get_track_info(&track).await;
+        if info.filter != filter {
+            let position = track_position(&track, &info).await;
+            if let Err(reason) = restart_current(ctx, guild_id, position).await {
+                msg.channel_id.say(&ctx.http, reason).await?;
+            }
+        }
+    }
+
+    msg.channel_id.say(&ctx.http, format!("Filter set to **{}**.", filter)).await?;
+
+    Ok(())
+}
+
+async fn soundboard_dir(ctx: &Context) -> PathBuf {
+    let data = ctx.data.read().await;
+    let config = data.get::<ConfigKey>().expect("Config placed in at init.");
+
+    PathBuf::from(config.soundboard_dir())
+}
+
+#[command]
+#[sub_commands(sb_add, sb_list, sb_rename, sb_delete)]
+#[usage = "<name> [interrupt] | add <name> | list | rename <name> <new name> | delete <name>"]
+#[description = "Plays a soundboard clip over the music, or pausing it until the clip is over."]
+async fn sb(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = match args.single::<String>() {
+        Ok(name) => name.to_lowercase(),
+        Err(_) => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: sb <name> [interrupt]").await?;
+            return Ok(());
+        },
+    };
+    let interrupt = args.single::<String>().is_ok_and(|arg| arg == "interrupt");
+
+    if interrupt && !dj_allowed(ctx, msg).await {
+        msg.channel_id.say(&ctx.http, ":warning: Interrupting the music needs the DJ role.").await?;
+        return Ok(());
+    }
+
+    let dir = soundboard_dir(ctx).await;
+    let path = match soundboard::find(&dir, &name) {
+        Some(path) => path,
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: There is no clip called `{}`. See `sb list`.", name)).await?;
+            return Ok(());
+        },
+    };
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let volume = guild_settings(ctx, msg.guild_id.unwrap()).await.volume;
+    let mut handler = handler_lock.lock().await;
+
+    // Only a playing track is paused, so that one paused on purpose stays that way.
+    let interrupted = match handler.queue().current() {
+        Some(track) if interrupt && track.get_info().await.is_ok_and(|state| state.playing == PlayMode::Play) => {
+            let _ = track.pause();
+            Some(track)
+        },
+        _ => None,
+    };
+
+    let clip = handler.play(Track::from(File::new(path)).volume(f32::from(volume) / 100.0));
+    if let Some(track) = interrupted {
+        for event in [TrackEvent::End, TrackEvent::Error] {
+            let _ = clip.add_event(event.into(), ResumeAfterClip { track: track.clone() });
+        }
+    }
+
+    Ok(())
+}
+
+#[command("add")]
+#[usage = "<name>"]
+#[description = "Adds the attached audio file as a clip. Admins only."]
+async fn sb_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can add clips.").await?;
+        return Ok(());
+    }
+
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    if !soundboard::valid_name(&name) {
+        msg.channel_id.say(&ctx.http,
+            ":warning: Clip names are up to 32 lowercase letters, digits, `-` or `_`, and cannot be a subcommand."
+        ).await?;
+        return Ok(());
+    }
+
+    let attachment = match msg.attachments.first()
+        .or_else(|| msg.referenced_message.as_ref().and_then(|referenced| referenced.attachments.first())) {
+        Some(attachment) => attachment,
+        None => {
+            msg.channel_id.say(&ctx.http, ":warning: Attach an audio file or reply to a message with one.").await?;
+            return Ok(());
+        },
+    };
+
+    let dir = soundboard_dir(ctx).await;
+    if soundboard::find(&dir, &name).is_some() {
+        msg.channel_id.say(&ctx.http, format!(":warning: A clip called `{}` already exists.", name)).await?;
+        return Ok(());
+    }
+
+    let extension = Path::new(&attachment.filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+        .filter(|extension| library::SUPPORTED_EXTENSIONS.contains(&extension.as_str()));
+    let extension = match extension {
+        Some(extension) => extension,
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: Clips must be one of: {}.",
+                library::SUPPORTED_EXTENSIONS.join(", "))).await?;
+            return Ok(());
+        },
+    };
+
+    let (max_size, max_length) = {
+        let data = ctx.data.read().await;
+        let config = data.get::<ConfigKey>().expect("Config placed in at init.");
+        (config.soundboard_max_size(), config.soundboard_max_length())
+    };
+    if u64::from(attachment.size) > max_size {
+        msg.channel_id.say(&ctx.http,
+            format!(":warning: Clips can be at most {}.", utils::to_size(max_size))
+        ).await?;
+        return Ok(());
+    }
+
+    let bytes = match get_http_client(ctx).await.get(&attachment.url).send().await {
+        Ok(response) => response.bytes().await,
+        Err(error) => Err(error),
+    };
+    let bytes = match bytes {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            println!("Failed to download clip {}: {:?}", attachment.url, error);
+            msg.channel_id.say(&ctx.http, format!(":warning: Could not download `{}`.", attachment.filename)).await?;
+            return Ok(());
+        },
+    };
+
+    let probe_bytes = bytes.clone();
+    let probe_extension = extension.clone();
+    let duration = tokio::task::spawn_blocking(move || {
+        library::probe(Box::new(Cursor::new(probe_bytes)), Some(&probe_extension))
+    }).await.ok().flatten().map(|metadata| metadata.duration);
+
+    match duration {
+        Some(Some(duration)) if duration.as_secs() <= max_length => {},
+        Some(Some(_)) => {
+            msg.channel_id.say(&ctx.http,
+                format!(":warning: Clips can be at most {} long.", utils::to_time(max_length))
+            ).await?;
+            return Ok(());
+        },
+        Some(None) => {
+            msg.channel_id.say(&ctx.http, ":warning: Could not tell how long that file is.").await?;
+            return Ok(());
+        },
+        None => {
+            msg.channel_id.say(&ctx.http,
+                format!(":warning: `{}` is not in a supported audio format.", attachment.filename)
+            ).await?;
+            return Ok(());
+        },
+    }
+
+    let path = dir.join(format!("{}.{}", name, extension));
+    let written = match tokio::fs::create_dir_all(&dir).await {
+        Ok(()) => tokio::fs::write(&path, &bytes).await,
+        Err(error) => Err(error),
+    };
+    if let Err(error) = written {
+        println!("Failed to save clip {:?}: {}", path, error);
+        msg.channel_id.say(&ctx.http, ":warning: Could not save the clip.").await?;
+        return Ok(());
+    }
+
+    msg.channel_id.say(&ctx.http, format!("Added clip `{}`. Play it with `sb {}`.", name, name)).await?;
+
+    Ok(())
+}
+
+#[command("list")]
+#[description = "Lists the soundboard clips."]
+async fn sb_list(ctx: &Context, msg: &Message) -> CommandResult {
+    let dir = soundboard_dir(ctx).await;
+    let clips = tokio::task::spawn_blocking(move || soundboard::clips(&dir))
+        .await
+        .unwrap_or_default();
+
+    if clips.is_empty() {
+        msg.channel_id.say(&ctx.http, "There are no clips yet.").await?;
+        return Ok(());
+    }
+
+    let mut description = String::new();
+    for clip in &clips {
+        let duration = clip.duration
+            .map_or(String::from("--:--"), |duration| utils::to_time(duration.as_secs()));
+        let _ = writeln!(description, "`{}` `{}`", clip.name, duration);
+    }
+
+    let embed = CreateEmbed::new()
+        .title(format!("Soundboard ({} clips)", clips.len()))
+        .description(description)
+        .footer(CreateEmbedFooter::new("Use sb <name> to play one"));
+    msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+#[command("rename")]
+#[usage = "<name> <new name>"]
+#[description = "Renames a clip. Admins only."]
+async fn sb_rename(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can rename clips.").await?;
+        return Ok(());
+    }
+
+    let (name, new_name) = match (args.single::<String>(), args.single::<String>()) {
+        (Ok(name), Ok(new_name)) => (name.to_lowercase(), new_name.to_lowercase()),
+        _ => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: sb rename <name> <new name>").await?;
+            return Ok(());
+        },
+    };
+
+    if !soundboard::valid_name(&new_name) {
+        msg.channel_id.say(&ctx.http,
+            ":warning: Clip names are up to 32 lowercase letters, digits, `-` or `_`, and cannot be a subcommand."
+        ).await?;
+        return Ok(());
+    }
+
+    let dir = soundboard_dir(ctx).await;
+    let path = match soundboard::find(&dir, &name) {
+        Some(path) => path,
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: There is no clip called `{}`.", name)).await?;
+            return Ok(());
+        },
+    };
+    if soundboard::find(&dir, &new_name).is_some() {
+        msg.channel_id.say(&ctx.http, format!(":warning: A clip called `{}` already exists.", new_name)).await?;
+        return Ok(());
+    }
+
+    let mut new_path = dir.join(&new_name);
+    if let Some(extension) = path.extension() {
+        new_path.set_extension(extension);
+    }
+    match tokio::fs::rename(&path, &new_path).await {
+        Ok(()) => msg.channel_id.say(&ctx.http, format!("Renamed clip `{}` to `{}`.", name, new_name)).await?,
+        Err(error) => {
+            println!("Failed to rename clip {:?}: {}", path, error);
+            msg.channel_id.say(&ctx.http, ":warning: Could not rename the clip.").await?
+        },
+    };
+
+    Ok(())
+}
+
+#[command("delete")]
+#[usage = "<name>"]
+#[description = "Deletes a clip. Admins only."]
+async fn sb_delete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can delete clips.").await?;
+        return Ok(());
+    }
+
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let path = match soundboard::find(&soundboard_dir(ctx).await, &name) {
+        Some(path) => path,
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: There is no clip called `{}`.", name)).await?;
+            return Ok(());
+        },
+    };
+
+    match tokio::fs::remove_file(&path).await {
+        Ok(()) => msg.channel_id.say(&ctx.http, format!("Deleted clip `{}`.", name)).await?,
+        Err(error) => {
+            println!("Failed to delete clip {:?}: {}", path, error);
+            msg.channel_id.say(&ctx.http, ":warning: Could not delete the clip.").await?
+        },
+    };
+
+    Ok(())
+}
+
+#[command]
+#[usage = "[page]"]
+#[description = "Lists the tracks played on this server, most recent first."]
+async fn history(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let entries = {
+        let data = ctx.data.read().await;
+        data.get::<HistoryKey>()
+            .cloned()
+            .expect("History placed in at init.")
+    }.read().await.entries(msg.guild_id.unwrap());
+
+    if entries.is_empty() {
+        msg.channel_id.say(&ctx.http, "Nothing has been played yet.").await?;
+
+        return Ok(());
+    }
+
+    let pages = entries.len().div_ceil(HISTORY_PAGE_SIZE);
+    let page = args.single::<usize>().unwrap_or(1).clamp(1, pages) - 1;
+
+    let mut description = String::new();
+    for (index, entry) in entries.iter().enumerate().skip(page * HISTORY_PAGE_SIZE).take(HISTORY_PAGE_SIZE) {
+        let requester = entry.requester
+            .map_or(String::from("unknown"), |user| format!("<@{}>", user));
+
+        let _ = writeln!(description, "`{}.` **{}** - {} <t:{}:R>",
+            index + 1, track_title(&entry.metadata()), requester, entry.finished_at);
+    }
+
+    let embed = CreateEmbed::new()
+        .title("Recently played")
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!("Page {}/{} | Use replay <number> to queue one again", page + 1, pages)));
+    msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("previous")]
+#[usage = "[number from history]"]
+#[description = "Queues a track from the history again, the last one played by default."]
+async fn replay(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let number = args.single::<usize>().unwrap_or(1).max(1);
+
+    let entry = {
+        let data = ctx.data.read().await;
+        data.get::<HistoryKey>()
+            .cloned()
+            .expect("History placed in at init.")
+    }.read().await.entries(msg.guild_id.unwrap()).into_iter().nth(number - 1);
+
+    let (entry, source) = match entry {
+        Some(entry) => match entry.source.clone() {
+            Some(source) => (entry, source),
+            None => {
+                msg.channel_id.say(&ctx.http, ":warning: That track cannot be played again.").await?;
+                return Ok(());
+            },
+        },
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: There is no entry {} in the history.", number)).await?;
+            return Ok(());
+        },
+    };
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let resolved = ResolvedSources {
+        name: None,
+        sources: vec![(source.to_input(get_http_client(ctx).await), entry.metadata(), source)],
+    };
+    add_to_queue(ctx, msg, handler_lock, resolved, false).await
+}
+
+async fn playlist_store(ctx: &Context) -> Arc<RwLock<Playlists>> {
+    let data = ctx.data.read().await;
+    data.get::<PlaylistsKey>()
+        .cloned()
+        .expect("Playlists placed in at init.")
+}
+
+async fn save_playlists(playlists: &Playlists) {
+    if let Err(error) = playlists.save().await {
+        println!("Failed to save playlists: {}", error);
+    }
+}
+
+/// Reads a playlist name, which may be quoted to contain spaces.
+fn playlist_name(args: &mut Args) -> Option<String> {
+    args.quoted().single::<String>().ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Finds a playlist the author may change: their own, or a server one they created or manage as DJ.
+/// `Err` holds a message for the user.
+async fn editable_playlist(ctx: &Context, msg: &Message, name: &str) -> Result<Scope, String> {
+    let found = playlist_store(ctx).await.read().await
+        .find(msg.author.id, msg.guild_id, name)
+        .map(|(scope, playlist)| (scope, playlist.created_by));
+
+    match found {
+        Some((Scope::Guild(_), created_by)) if created_by != msg.author.id && !is_dj(ctx, msg).await =>
+            Err(String::from(":warning: Only its creator or a DJ can change this server playlist.")),
+        Some((scope, _)) => Ok(scope),
+        None => Err(format!(":warning: There is no playlist called **{}**.", name)),
+    }
+}
+
+/// Appends tracks to a playlist, keeping it within the configured playlist limit.
+async fn add_to_playlist(ctx: &Context, scope: Scope, name: &str, tracks: Vec<SavedTrack>) -> String {
+    let limit = {
+        let data = ctx.data.read().await;
+        data.get::<ConfigKey>()
+            .expect("Config placed in at init.")
+            .playlist_limit()
+    };
+
+    let playlists = playlist_store(ctx).await;
+    let mut playlists = playlists.write().await;
+    let playlist = match playlists.get_mut(scope, name) {
+        Some(playlist) => playlist,
+        None => return format!(":warning: There is no playlist called **{}**.", name),
+    };
+
+    let room = limit.saturating_sub(playlist.tracks.len());
+    if room == 0 {
+        return format!(":warning: **{}** already holds the maximum of {} tracks.", playlist.name, limit);
+    }
+
+    let added = match tracks.as_slice() {
+        [track] => format!("**{}**", track_title(&track.metadata())),
+        tracks => format!("{} tracks", tracks.len().min(room)),
+    };
+    let skipped = tracks.len().saturating_sub(room);
+    playlist.tracks.extend(tracks.into_iter().take(room));
+
+    let mut reply = format!("Added {} to **{}**. Tracks in the playlist: {}.", added, playlist.name, playlist.tracks.len());
+    if skipped > 0 {
+        let _ = write!(reply, " {} skipped, playlists hold at most {} tracks.", count_tracks(skipped), limit);
+    }
+    save_playlists(&playlists).await;
+
+    reply
+}
+
+#[command]
+#[sub_commands(playlist_create, playlist_add, playlist_addcurrent, playlist_remove, playlist_show, playlist_load,
+playlist_delete, playlist_rename)]
+#[aliases("pl")]
+#[usage = "[create | add | addcurrent | remove | show | load | delete | rename]"]
+#[description = "Lists your playlists and this server's. Names with spaces must be quoted."]
+async fn playlist(ctx: &Context, msg: &Message) -> CommandResult {
+    let playlists = playlist_store(ctx).await;
+    let playlists = playlists.read().await;
+
+    let list = |scope: Scope| {
+        let lines: Vec<String> = playlists.list(scope).iter()
+            .map(|playlist| format!("**{}** ({})", playlist.name, count_tracks(playlist.tracks.len())))
+            .collect();
+
+        if lines.is_empty() { String::from("None yet.") } else { lines.join("\n") }
+    };
+
+    let embed = CreateEmbed::new()
+        .title("Playlists")
+        .field("Yours", list(Scope::User(msg.author.id)), false)
+        .field("This server", list(Scope::Guild(msg.guild_id.unwrap())), false)
+        .footer(CreateEmbedFooter::new("Use playlist load <name> to queue one"));
+    msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        let _ = queue.skip();
+#[command("create")]
+#[usage = "<name> [server]"]
+#[description = "Creates an empty playlist of your own, or one shared with the server."]
+async fn playlist_create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = match playlist_name(&mut args) {
+        Some(name) => name,
+        None => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist create <name> [server]").await?;
+            return Ok(());
+        },
+    };
 
-        if queue.len() < 1 {
-            msg.channel_id.say(&ctx.http, "No more songs in a queue.").await;
-        }
-        msg.channel_id.say(&ctx.http, format!("Playing: {:?}", handler.current_connection())).await;
-        msg.channel_id.say(&ctx.http, format!("Song skipped: {} left in the queue.", queue.len() - 1)).await;
-    } else {
-        msg.channel_id.say(&ctx.http, "Not in a voice channel to play in.").await;
+    let scope = match args.single::<String>() {
+        Ok(scope) if scope == "server" => {
+            if !dj_allowed(ctx, msg).await {
+                msg.channel_id.say(&ctx.http, ":warning: Creating server playlists needs the DJ role.").await?;
+                return Ok(());
+            }
+            Scope::Guild(msg.guild_id.unwrap())
+        },
+        _ => Scope::User(msg.author.id),
+    };
+
+    let playlists = playlist_store(ctx).await;
+    let mut playlists = playlists.write().await;
+    if !playlists.create(scope, &name, msg.author.id) {
+        msg.channel_id.say(&ctx.http, format!(":warning: A playlist called **{}** already exists.", name)).await?;
+        return Ok(());
     }
+    save_playlists(&playlists).await;
+
+    let owner = match scope {
+        Scope::User(_) => "your",
+        Scope::Guild(_) => "a server",
+    };
+    msg.channel_id.say(&ctx.http, format!("Created {} playlist **{}**.", owner, name)).await?;
 
     Ok(())
 }
 
-#[command]
-async fn queue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let url = match args.single::<String>() {
-        Ok(url) => url,
-        Err(_) => {
-            msg.channel_id.say(&ctx.http, "Must provide url for audio or video.")
-                .await;
+#[command("add")]
+#[usage = "<name> <url|query>"]
+#[description = "Adds a track, album or playlist to a saved playlist."]
+async fn playlist_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (name, url) = match (playlist_name(&mut args), args.rest().trim()) {
+        (Some(name), url) if !url.is_empty() => (name, url.to_string()),
+        _ => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist add <name> <url|query>").await?;
+            return Ok(());
+        },
+    };
 
+    let scope = match editable_playlist(ctx, msg, &name).await {
+        Ok(scope) => scope,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
             return Ok(());
         },
     };
 
-    if !url.starts_with("http") {
-        msg.channel_id.say(&ctx.http, "Must provide a valid URL.").await;
+    let tracks = match resolve_sources(ctx, url, false).await {
+        Ok(resolved) => resolved.sources.into_iter()
+            .map(|(_, metadata, source)| SavedTrack::new(metadata, source))
+            .collect(),
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
 
-        return Ok(());
+    let reply = add_to_playlist(ctx, scope, &name, tracks).await;
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("addcurrent")]
+#[usage = "<name>"]
+#[description = "Adds the track that is playing now to a saved playlist."]
+async fn playlist_addcurrent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = match playlist_name(&mut args) {
+        Some(name) => name,
+        None => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist addcurrent <name>").await?;
+            return Ok(());
+        },
+    };
+
+    let scope = match editable_playlist(ctx, msg, &name).await {
+        Ok(scope) => scope,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let info = match current_track(ctx, msg.guild_id.unwrap()).await {
+        Ok(track) => get_track_info(&track).await,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let source = match info.source {
+        Some(source) => source,
+        None => {
+            msg.channel_id.say(&ctx.http, ":warning: The current track cannot be saved.").await?;
+            return Ok(());
+        },
+    };
+
+    let reply = add_to_playlist(ctx, scope, &name, vec![SavedTrack::new(info.metadata, source)]).await;
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("remove")]
+#[usage = "<name> <position>"]
+#[description = "Removes a track from a saved playlist."]
+async fn playlist_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (name, position) = match (playlist_name(&mut args), args.single::<usize>()) {
+        (Some(name), Ok(position)) => (name, position),
+        _ => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist remove <name> <position>").await?;
+            return Ok(());
+        },
+    };
+
+    let scope = match editable_playlist(ctx, msg, &name).await {
+        Ok(scope) => scope,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let playlists = playlist_store(ctx).await;
+    let mut playlists = playlists.write().await;
+    let reply = match playlists.get_mut(scope, &name) {
+        Some(playlist) if (1..=playlist.tracks.len()).contains(&position) => {
+            let removed = playlist.tracks.remove(position - 1);
+            format!("Removed **{}** from **{}**.", track_title(&removed.metadata()), playlist.name)
+        },
+        Some(playlist) => format!(":warning: **{}** has no track {}.", playlist.name, position),
+        None => format!(":warning: There is no playlist called **{}**.", name),
+    };
+    save_playlists(&playlists).await;
+
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("show")]
+#[usage = "<name> [page]"]
+#[description = "Lists the tracks of a saved playlist."]
+async fn playlist_show(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = match playlist_name(&mut args) {
+        Some(name) => name,
+        None => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist show <name> [page]").await?;
+            return Ok(());
+        },
+    };
+
+    let playlist = playlist_store(ctx).await.read().await
+        .find(msg.author.id, msg.guild_id, &name)
+        .map(|(_, playlist)| playlist.clone());
+
+    let playlist = match playlist {
+        Some(playlist) if playlist.tracks.is_empty() => {
+            msg.channel_id.say(&ctx.http, format!("**{}** is empty.", playlist.name)).await?;
+            return Ok(());
+        },
+        Some(playlist) => playlist,
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: There is no playlist called **{}**.", name)).await?;
+            return Ok(());
+        },
+    };
+
+    let pages = playlist.tracks.len().div_ceil(QUEUE_PAGE_SIZE);
+    let page = args.single::<usize>().unwrap_or(1).clamp(1, pages) - 1;
+
+    let mut description = String::new();
+    for (index, track) in playlist.tracks.iter().enumerate().skip(page * QUEUE_PAGE_SIZE).take(QUEUE_PAGE_SIZE) {
+        let duration = track.duration.map_or(String::from("live"), utils::to_time);
+        let _ = writeln!(description, "`{}.` **{}** `[{}]`", index + 1, track_title(&track.metadata()), duration);
     }
 
-    let guild_id = msg.guild_id.unwrap();
+    let total: u64 = playlist.tracks.iter().filter_map(|track| track.duration).sum();
+    let embed = CreateEmbed::new()
+        .title(format!("{} ({})", playlist.name, count_tracks(playlist.tracks.len())))
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{} | Total time: {} | Created by {}", page + 1, pages, utils::to_time(total),
+            playlist.created_by.to_user(&ctx).await.map_or(String::from("unknown"), |user| user.name)
+        )));
+    msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+#[command("load")]
+#[usage = "<name> [shuffle]"]
+#[description = "Adds a whole saved playlist to the queue."]
+async fn playlist_load(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = match playlist_name(&mut args) {
+        Some(name) => name,
+        None => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist load <name> [shuffle]").await?;
+            return Ok(());
+        },
+    };
+    let shuffle = args.single::<String>().is_ok_and(|arg| arg == "shuffle");
+
+    let playlist = playlist_store(ctx).await.read().await
+        .find(msg.author.id, msg.guild_id, &name)
+        .map(|(_, playlist)| playlist.clone());
+
+    let mut playlist = match playlist {
+        Some(playlist) if playlist.tracks.is_empty() => {
+            msg.channel_id.say(&ctx.http, format!(":warning: **{}** is empty.", playlist.name)).await?;
+            return Ok(());
+        },
+        Some(playlist) => playlist,
+        None => {
+            msg.channel_id.say(&ctx.http, format!(":warning: There is no playlist called **{}**.", name)).await?;
+            return Ok(());
+        },
+    };
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    if shuffle {
+        playlist.tracks.shuffle(&mut rand::thread_rng());
+    }
 
     let http_client = get_http_client(ctx).await;
+    let resolved = ResolvedSources {
+        name: Some(playlist.name),
+        sources: playlist.tracks.into_iter()
+            .map(|track| (track.source.to_input(http_client.clone()), track.metadata(), track.source))
+            .collect(),
+    };
+    add_to_queue(ctx, msg, handler_lock, resolved, false).await
+}
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+#[command("delete")]
+#[usage = "<name>"]
+#[description = "Deletes a saved playlist."]
+async fn playlist_delete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = match playlist_name(&mut args) {
+        Some(name) => name,
+        None => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist delete <name>").await?;
+            return Ok(());
+        },
+    };
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
+    let scope = match editable_playlist(ctx, msg, &name).await {
+        Ok(scope) => scope,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
 
-        let src = YoutubeDl::new(http_client, url);
-        handler.enqueue_input(src.into()).await;
+    let playlists = playlist_store(ctx).await;
+    let mut playlists = playlists.write().await;
+    let reply = match playlists.delete(scope, &name) {
+        Some(playlist) => format!("Deleted **{}**.", playlist.name),
+        None => format!(":warning: There is no playlist called **{}**.", name),
+    };
+    save_playlists(&playlists).await;
 
-        msg.channel_id.say(&ctx.http,
-            format!("Added song to the queue. Songs in the queue: {}", handler.queue().len())
-        ).await;
-    } else {
-        msg.channel_id.say(&ctx.http, "Not in a voice channel to play in").await;
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("rename")]
+#[usage = "<name> <new name>"]
+#[description = "Renames a saved playlist."]
+async fn playlist_rename(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (name, new_name) = match (playlist_name(&mut args), playlist_name(&mut args)) {
+        (Some(name), Some(new_name)) => (name, new_name),
+        _ => {
+            msg.channel_id.say(&ctx.http, ":warning: Use command like this: playlist rename <name> <new name>").await?;
+            return Ok(());
+        },
+    };
+
+    let scope = match editable_playlist(ctx, msg, &name).await {
+        Ok(scope) => scope,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let playlists = playlist_store(ctx).await;
+    let mut playlists = playlists.write().await;
+    if !new_name.eq_ignore_ascii_case(&name) && playlists.get_mut(scope, &new_name).is_some() {
+        msg.channel_id.say(&ctx.http, format!(":warning: A playlist called **{}** already exists.", new_name)).await?;
+        return Ok(());
     }
 
+    let reply = match playlists.get_mut(scope, &name) {
+        Some(playlist) => {
+            let reply = format!("Renamed **{}** to **{}**.", playlist.name, new_name);
+            playlist.name = new_name;
+            reply
+        },
+        None => format!(":warning: There is no playlist called **{}**.", name),
+    };
+    save_playlists(&playlists).await;
+
+    msg.channel_id.say(&ctx.http, reply).await?;
+
     Ok(())
 }
 
 #[command]
-async fn reset_queue(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    let guild_id = msg.guild_id.unwrap();
+#[description = "Toggles queueing related tracks once the queue runs out."]
+async fn autoplay(ctx: &Context, msg: &Message) -> CommandResult {
+    if !dj_allowed(ctx, msg).await {
+        msg.channel_id.say(&ctx.http, ":warning: Toggling autoplay needs the DJ role.").await?;
+        return Ok(());
+    }
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+    let settings = update_guild_settings(ctx, msg.guild_id.unwrap(), |settings| {
+        settings.autoplay = !settings.autoplay;
+    }).await;
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        queue.stop();
+    let reply = if settings.autoplay {
+        "Autoplay enabled, I will keep playing related tracks when the queue runs out."
+    } else {
+        "Autoplay disabled, I will stop when the queue runs out."
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
 
-        msg.channel_id.say(&ctx.http, "Queue cleared.").await;
+    Ok(())
+}
+
+#[command]
+#[description = "Toggles resuming the queue after a restart without asking first. Admins only."]
+async fn autoresume(ctx: &Context, msg: &Message) -> CommandResult {
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can toggle auto-resume.").await?;
+        return Ok(());
+    }
+
+    let settings = update_guild_settings(ctx, msg.guild_id.unwrap(), |settings| {
+        settings.auto_resume = !settings.auto_resume;
+    }).await;
+
+    let reply = if settings.auto_resume {
+        "Auto-resume enabled, I will rejoin and pick up the queue after a restart."
     } else {
-        msg.channel_id.say(&ctx.http, "Not in voice channel.").await;
+        "Auto-resume disabled, I will ask before picking up the queue after a restart."
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("247")]
+#[aliases("24/7")]
+#[description = "Toggles staying in the voice channel while idle or alone. Admins only."]
+async fn always_on(ctx: &Context, msg: &Message) -> CommandResult {
+    if !is_admin(ctx, msg) {
+        msg.channel_id.say(&ctx.http, ":warning: Only admins can toggle 24/7 mode.").await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.unwrap();
+    let settings = update_guild_settings(ctx, guild_id, |settings| {
+        settings.always_on = !settings.always_on;
+    }).await;
+    update_alone_timer(ctx, guild_id).await;
+
+    let reply = if settings.always_on {
+        "24/7 mode enabled, I will stay in the voice channel."
+    } else {
+        "24/7 mode disabled, I will leave when idle or alone."
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("loop")]
+#[usage = "[off|track|queue]"]
+#[description = "Shows or sets whether the current track or the whole queue repeats."]
+async fn loop_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    if args.is_empty() {
+        let loop_mode = guild_loop_mode(ctx, guild_id).await;
+        msg.channel_id.say(&ctx.http, format!("Loop is set to **{}**.", loop_mode)).await?;
+
+        return Ok(());
+    }
+
+    let loop_mode = match args.single::<String>().unwrap_or_default().to_lowercase().as_str() {
+        "off" => LoopMode::Off,
+        "track" => LoopMode::Track,
+        "queue" => LoopMode::Queue,
+        _ => {
+            msg.channel_id.say(&ctx.http, ":warning: Loop mode must be `off`, `track` or `queue`.").await?;
+            return Ok(());
+        },
+    };
+
+    set_loop_mode(ctx, guild_id, loop_mode).await;
+
+    msg.channel_id.say(&ctx.http, format!("Loop set to **{}**.", loop_mode)).await?;
+
+    Ok(())
+}
+
+async fn set_loop_mode(ctx: &Context, guild_id: GuildId, loop_mode: LoopMode) {
+    guild_states(ctx).await
+        .write().await
+        .entry(guild_id)
+        .or_default()
+        .loop_mode = loop_mode;
+
+    // Later tracks pick the mode up when they start, the current one is switched here.
+    if let Ok(track) = current_track(ctx, guild_id).await {
+        let _ = match loop_mode {
+            LoopMode::Track => track.enable_loop(),
+            _ => track.disable_loop(),
+        };
+    }
+}
+
+#[command]
+#[sub_commands(library_search)]
+#[usage = "[page] | search <query>"]
+#[description = "Lists the local media library."]
+async fn library(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let entries = library_entries(ctx).await;
+
+    if entries.is_empty() {
+        msg.channel_id.say(&ctx.http, "The library is empty.").await?;
+
+        return Ok(());
+    }
+
+    let pages = entries.len().div_ceil(LIBRARY_PAGE_SIZE);
+    let page = args.single::<usize>().unwrap_or(1).clamp(1, pages);
+
+    let lines = library_lines(entries.iter().enumerate()
+        .skip((page - 1) * LIBRARY_PAGE_SIZE)
+        .take(LIBRARY_PAGE_SIZE));
+
+    let embed = CreateEmbed::new()
+        .title(format!("Library ({} files)", entries.len()))
+        .description(lines)
+        .footer(CreateEmbedFooter::new(format!("Page {}/{} | Use playfile <number> to play", page, pages)));
+
+    msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+#[command("search")]
+#[usage = "<query>"]
+#[description = "Searches the local media library by title, artist, album or file name."]
+async fn library_search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let query = args.rest().trim();
+
+    if query.is_empty() {
+        msg.channel_id.say(&ctx.http, ":warning: Use command like this: library search <query>").await?;
+
+        return Ok(());
+    }
+
+    let entries = library_entries(ctx).await;
+    let lines = library_lines(entries.iter().enumerate()
+        .filter(|(_, entry)| entry.matches(query))
+        .take(LIBRARY_PAGE_SIZE));
+
+    if lines.is_empty() {
+        msg.channel_id.say(&ctx.http, format!("Nothing in the library matches `{}`.", query)).await?;
+
+        return Ok(());
     }
 
+    let embed = CreateEmbed::new()
+        .title(format!("Library results for \"{}\"", query))
+        .description(lines);
+
+    msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
     Ok(())
 }
 
+#[command]
+#[usage = "<number|name>"]
+#[description = "Adds a file from the local media library to the queue."]
+async fn playfile(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let query = args.rest().trim();
+
+    if query.is_empty() {
+        msg.channel_id.say(&ctx.http, ":warning: Use command like this: playfile <number|name>").await?;
+
+        return Ok(());
+    }
+
+    let entries = library_entries(ctx).await;
+    let entry = match query.parse::<usize>() {
+        Ok(number) => entries.into_iter().nth(number.wrapping_sub(1)),
+        Err(_) => entries.into_iter().find(|entry| entry.matches(query)),
+    };
+
+    let entry = match entry {
+        Some(entry) => entry,
+        None => {
+            msg.channel_id.say(&ctx.http, format!("Nothing in the library matches `{}`.", query)).await?;
+
+            return Ok(());
+        },
+    };
+
+    let handler_lock = match ensure_voice(ctx, msg).await {
+        Ok(handler_lock) => handler_lock,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+            return Ok(());
+        },
+    };
+
+    let resolved = ResolvedSources {
+        name: None,
+        sources: vec![(File::new(entry.path.clone()).into(), entry.metadata, TrackSource::File(entry.path))],
+    };
+    add_to_queue(ctx, msg, handler_lock, resolved, false).await
+}
+
 #[command]
 async fn join(ctx: &Context, msg: &Message) -> CommandResult {
     // println!("{:?}\n{:?}", msg, _args);
@@ -390,23 +4233,17 @@ async fn join(ctx: &Context, msg: &Message) -> CommandResult {
     let connect_to = match channel_id {
         Some(channel) => channel,
         None => {
-            msg.channel_id.say(&ctx.http, ":warning: Join a voice channel first!");
+            msg.channel_id.say(&ctx.http, ":warning: Join a voice channel first!").await?;
 
             return Ok(());
         }
     };
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Songbird voice client init.")
-        .clone();
-
-    if let Ok(handler_lock) = manager.join(guild_id, connect_to).await {
-        let mut handler = handler_lock.lock().await;
-        handler.add_global_event(TrackEvent::Error.into(), TrackErrorNotifier);
-    } else {
-        msg.channel_id.say(&ctx.http, ":warning: Error joining channel. \
-        Please ensure I have the correct permissions.").await;
+    match join_channel(ctx, guild_id, connect_to).await {
+        Ok(_) => remember_text_channel(ctx, msg).await,
+        Err(reason) => {
+            msg.channel_id.say(&ctx.http, reason).await?;
+        },
     }
 
     Ok(())
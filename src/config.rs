@@ -3,6 +3,8 @@ use ron::{ser, de};
 use ron::ser::PrettyConfig;
 use serde::{Serialize, Deserialize};
 use std::io::Write;
+use std::sync::Arc;
+use serenity::prelude::TypeMapKey;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -12,6 +14,28 @@ pub struct Config {
     spotify_client_id: String,
     spotify_client_secret: String,
     spotify_redirect_uri: String,
+    playlist_limit: usize,
+    library_dir: String,
+    attachment_max_size: u64,
+    attachment_types: Vec<String>,
+    settings_path: String,
+    idle_timeout: u64,
+    skip_fraction: f64,
+    history_size: usize,
+    history_path: String,
+    playlists_path: String,
+    queues_path: String,
+    cache_dir: String,
+    cache_size: u64,
+    soundboard_dir: String,
+    soundboard_max_size: u64,
+    soundboard_max_length: u64,
+}
+
+pub(crate) struct ConfigKey;
+
+impl TypeMapKey for ConfigKey {
+    type Value = Arc<Config>;
 }
 
 impl Config {
@@ -22,6 +46,30 @@ impl Config {
         let client_secret = env::var("CLIENT_SECRET").unwrap();
         let redirect_uri = env::var("SPOTIFY_REDIRECT_URI").unwrap();
         let aut_id = env::var("AUTHOR_ID").unwrap();
+        let playlist_limit = env::var("PLAYLIST_LIMIT").unwrap_or(String::from("100"));
+        let library_dir = env::var("LIBRARY_DIR").unwrap_or(String::from("library"));
+        let attachment_max_size = env::var("ATTACHMENT_MAX_SIZE").unwrap_or(String::from("26214400"));
+        let attachment_types = env::var("ATTACHMENT_TYPES").unwrap_or(String::from("audio/,video/mp4"));
+        let settings_path = env::var("SETTINGS_PATH").unwrap_or(String::from("guilds.ron"));
+        let idle_timeout = env::var("IDLE_TIMEOUT").unwrap_or(String::from("300"));
+        let skip_fraction = env::var("SKIP_FRACTION").unwrap_or(String::from("0.5"));
+        let history_size = env::var("HISTORY_SIZE").unwrap_or(String::from("50"));
+        let history_path = env::var("HISTORY_PATH").unwrap_or(String::from("history.ron"));
+        let playlists_path = env::var("PLAYLISTS_PATH").unwrap_or(String::from("playlists.ron"));
+        let queues_path = env::var("QUEUES_PATH").unwrap_or(String::from("queues.ron"));
+        let cache_dir = env::var("CACHE_DIR").unwrap_or(String::from("cache"));
+        let cache_size = env::var("CACHE_SIZE").unwrap_or(String::from("2147483648"));
+        let soundboard_dir = env::var("SOUNDBOARD_DIR").unwrap_or(String::from("sounds"));
+        let soundboard_max_size = env::var("SOUNDBOARD_MAX_SIZE").unwrap_or(String::from("1048576"));
+        let soundboard_max_length = env::var("SOUNDBOARD_MAX_LENGTH").unwrap_or(String::from("15"));
+
+        let skip_fraction = match skip_fraction.trim().parse::<f64>() {
+            Ok(fraction) if is_valid_skip_fraction(fraction) => fraction,
+            _ => {
+                println!("SKIP_FRACTION must be a number above 0 and at most 1, got '{}'.", skip_fraction);
+                std::process::exit(1);
+            }
+        };
 
       return Config {
           token: dc_token,
@@ -30,6 +78,22 @@ impl Config {
           spotify_client_id: client_id,
           spotify_client_secret: client_secret,
           spotify_redirect_uri: redirect_uri,
+          playlist_limit: playlist_limit.parse::<usize>().unwrap(),
+          library_dir,
+          attachment_max_size: attachment_max_size.parse::<u64>().unwrap(),
+          attachment_types: attachment_types.split(',').map(String::from).collect(),
+          settings_path,
+          idle_timeout: idle_timeout.parse::<u64>().unwrap(),
+          skip_fraction,
+          history_size: history_size.parse::<usize>().unwrap(),
+          history_path,
+          playlists_path,
+          queues_path,
+          cache_dir,
+          cache_size: cache_size.parse::<u64>().unwrap(),
+          soundboard_dir,
+          soundboard_max_size: soundboard_max_size.parse::<u64>().unwrap(),
+          soundboard_max_length: soundboard_max_length.parse::<u64>().unwrap(),
       }
     }
 
@@ -62,6 +126,10 @@ impl Config {
                 std::process::exit(1);
             }
         };
+        if !is_valid_skip_fraction(config.skip_fraction) {
+            println!("Failed to load config: skip_fraction must be above 0 and at most 1, got {}.", config.skip_fraction);
+            std::process::exit(1);
+        }
 
         return Ok(config)
     }
@@ -75,4 +143,60 @@ impl Config {
     pub fn spotify_client_secret(&self) -> &String { return &self.spotify_client_secret; }
 
     pub fn spotify_redirect_uri(&self) -> &String { return &self.spotify_redirect_uri; }
-}
\ No newline at end of file
+
+    pub fn playlist_limit(&self) -> usize { return self.playlist_limit; }
+
+    pub fn library_dir(&self) -> &String { return &self.library_dir; }
+
+    pub fn attachment_max_size(&self) -> u64 { return self.attachment_max_size; }
+
+    pub fn attachment_types(&self) -> &Vec<String> { return &self.attachment_types; }
+
+    pub fn settings_path(&self) -> &String { return &self.settings_path; }
+
+    pub fn idle_timeout(&self) -> u64 { return self.idle_timeout; }
+
+    pub fn skip_fraction(&self) -> f64 { return self.skip_fraction; }
+
+    pub fn history_size(&self) -> usize { return self.history_size; }
+
+    /// Empty when history is kept in memory only.
+    pub fn history_path(&self) -> &String { return &self.history_path; }
+
+    pub fn playlists_path(&self) -> &String { return &self.playlists_path; }
+
+    pub fn queues_path(&self) -> &String { return &self.queues_path; }
+
+    pub fn cache_dir(&self) -> &String { return &self.cache_dir; }
+
+    /// Size budget of the audio cache in bytes, 0 to turn it off. Tracks are downloaded a second
+    /// time once they finished streaming, so the first play of a track costs twice the bandwidth.
+    pub fn cache_size(&self) -> u64 { return self.cache_size; }
+
+    pub fn soundboard_dir(&self) -> &String { return &self.soundboard_dir; }
+
+    pub fn soundboard_max_size(&self) -> u64 { return self.soundboard_max_size; }
+
+    /// Longest clip accepted on upload, in seconds.
+    pub fn soundboard_max_length(&self) -> u64 { return self.soundboard_max_length; }
+}
+
+/// Votes to skip are counted as a share of the listeners, so only (0, 1] makes sense.
+fn is_valid_skip_fraction(fraction: f64) -> bool {
+    fraction > 0.0 && fraction <= 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_fraction_must_be_a_share() {
+        assert!(is_valid_skip_fraction(0.5));
+        assert!(is_valid_skip_fraction(1.0));
+        assert!(!is_valid_skip_fraction(0.0));
+        assert!(!is_valid_skip_fraction(1.5));
+        assert!(!is_valid_skip_fraction(-0.5));
+        assert!(!is_valid_skip_fraction(f64::NAN));
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use songbird::input::{AudioStream, AudioStreamError, ChildContainer, Compose, Input};
+use symphonia::core::io::{MediaSource, ReadOnlySource};
+use symphonia::core::probe::Hint;
+
+/// Sample rate ffmpeg resamples to, and the one songbird mixes at.
+const SAMPLE_RATE: u32 = 48_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+    #[default]
+    Off,
+    BassBoost,
+    Nightcore,
+    Vaporwave,
+    Karaoke,
+    EightD,
+    /// Changes tempo while keeping the pitch.
+    Speed(f64),
+    /// Changes pitch while keeping the tempo.
+    Pitch(f64),
+}
+
+impl Filter {
+    /// Parses a preset name, with the factor `speed` and `pitch` take in the 0.5-2.0 range.
+    pub fn parse(name: &str, factor: Option<&str>) -> Option<Filter> {
+        let factor = factor
+            .and_then(|factor| factor.trim_end_matches('x').parse::<f64>().ok())
+            .filter(|factor| (0.5..=2.0).contains(factor));
+
+        match name {
+            "off" | "none" => Some(Filter::Off),
+            "bassboost" => Some(Filter::BassBoost),
+            "nightcore" => Some(Filter::Nightcore),
+            "vaporwave" => Some(Filter::Vaporwave),
+            "karaoke" => Some(Filter::Karaoke),
+            "8d" => Some(Filter::EightD),
+            "speed" => factor.map(Filter::Speed),
+            "pitch" => factor.map(Filter::Pitch),
+            _ => None,
+        }
+    }
+
+    /// How many seconds of the source play per second of output.
+    pub fn tempo(&self) -> f64 {
+        match self {
+            Filter::Nightcore => 1.25,
+            Filter::Vaporwave => 0.8,
+            Filter::Speed(factor) => *factor,
+            _ => 1.0,
+        }
+    }
+
+    /// The ffmpeg `-af` chain for this preset.
+    fn audio_filter(&self) -> Option<String> {
+        let resample = |factor: f64| format!(
+            "aresample={rate},asetrate={rate}*{factor},aresample={rate}", rate = SAMPLE_RATE, factor = factor
+        );
+
+        match self {
+            Filter::Off => None,
+            Filter::BassBoost => Some(String::from("bass=g=10:f=110:w=0.6")),
+            Filter::Nightcore => Some(resample(1.25)),
+            Filter::Vaporwave => Some(resample(0.8)),
+            // Vocals are usually mixed to the centre, which cancels out when subtracting the channels.
+            Filter::Karaoke => Some(String::from("pan=stereo|c0=c0-c1|c1=c1-c0")),
+            Filter::EightD => Some(String::from("apulsator=hz=0.125")),
+            Filter::Speed(factor) => Some(format!("atempo={}", factor)),
+            Filter::Pitch(factor) => Some(format!("{},atempo={}", resample(*factor), 1.0 / factor)),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Off => write!(f, "off"),
+            Filter::BassBoost => write!(f, "bassboost"),
+            Filter::Nightcore => write!(f, "nightcore"),
+            Filter::Vaporwave => write!(f, "vaporwave"),
+            Filter::Karaoke => write!(f, "karaoke"),
+            Filter::EightD => write!(f, "8d"),
+            Filter::Speed(factor) => write!(f, "speed {}x", factor),
+            Filter::Pitch(factor) => write!(f, "pitch {}x", factor),
+        }
+    }
+}
+
+/// What ffmpeg reads from: a yt-dlp download piped in, or a file or URL it opens itself.
+#[derive(Clone, Debug)]
+pub(crate) enum FilterSource {
+    YtDlp(String),
+    Direct(String),
+}
+
+/// A source played through an ffmpeg filter chain, starting at `start` into the source.
+///
+/// The processes are only spawned once songbird readies the track.
+pub(crate) struct FilteredInput {
+    pub source: FilterSource,
+    pub filter: Filter,
+    pub start: Duration,
+}
+
+impl FilteredInput {
+    fn spawn(&self) -> std::io::Result<ChildContainer> {
+        let mut children = Vec::with_capacity(2);
+
+        let input = match &self.source {
+            FilterSource::YtDlp(url) => {
+                let mut ytdl = Command::new("yt-dlp")
+                    .args(["-f", "bestaudio/best", "--no-playlist", "--quiet", "-o", "-", url])
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()?;
+                let stdout = ytdl.stdout.take().expect("Piped above.");
+                children.push(ytdl);
+
+                (String::from("pipe:0"), Stdio::from(stdout))
+            },
+            FilterSource::Direct(path) => (path.clone(), Stdio::null()),
+        };
+
+        let mut ffmpeg = Command::new("ffmpeg");
+        ffmpeg.args(["-loglevel", "error", "-ss", &format!("{:.3}", self.start.as_secs_f64()), "-i", &input.0]);
+        if let Some(filter) = self.filter.audio_filter() {
+            ffmpeg.args(["-af", &filter]);
+        }
+        let ffmpeg = ffmpeg
+            .args(["-vn", "-ac", "2", "-ar", &SAMPLE_RATE.to_string(), "-f", "wav", "-acodec", "pcm_s16le", "pipe:1"])
+            .stdin(input.1)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        children.push(ffmpeg);
+
+        Ok(ChildContainer::new(children))
+    }
+}
+
+#[async_trait]
+impl Compose for FilteredInput {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let container = self.spawn().map_err(|error| AudioStreamError::Fail(Box::new(error)))?;
+
+        let mut hint = Hint::new();
+        hint.with_extension("wav");
+
+        Ok(AudioStream {
+            input: Box::new(ReadOnlySource::new(container)),
+            hint: Some(hint),
+        })
+    }
+
+    async fn create_async(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    fn should_create_async(&self) -> bool {
+        false
+    }
+}
+
+impl From<FilteredInput> for Input {
+    fn from(input: FilteredInput) -> Self {
+        Input::Lazy(Box::new(input))
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::{RwLock, TypeMapKey};
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler};
+use songbird::input::AuxMetadata;
+
+use crate::commands::{TrackInfo, TrackInfoKey, TrackSource};
+use crate::storage;
+
+/// How long changes are collected before the history file is rewritten, so that tracks ending
+/// close together cost a single write.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// A track that finished playing, newest first in `History`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub url: Option<String>,
+    pub thumbnail: Option<String>,
+    pub duration: Option<u64>,
+    pub source: Option<TrackSource>,
+    pub requester: Option<UserId>,
+    /// Unix timestamp of when the track stopped playing.
+    pub finished_at: u64,
+}
+
+impl HistoryEntry {
+    fn new(info: TrackInfo) -> Self {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+
+        HistoryEntry {
+            title: info.metadata.title.or(info.metadata.track),
+            artist: info.metadata.artist,
+            url: info.metadata.source_url,
+            thumbnail: info.metadata.thumbnail,
+            duration: info.metadata.duration.map(|duration| duration.as_secs()),
+            source: info.source,
+            requester: info.requester,
+            finished_at,
+        }
+    }
+
+    pub fn metadata(&self) -> AuxMetadata {
+        AuxMetadata {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            source_url: self.url.clone(),
+            thumbnail: self.thumbnail.clone(),
+            duration: self.duration.map(Duration::from_secs),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct History {
+    guilds: HashMap<u64, VecDeque<HistoryEntry>>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(skip)]
+    capacity: usize,
+    #[serde(skip)]
+    save_scheduled: bool,
+}
+
+pub(crate) struct HistoryKey;
+
+impl TypeMapKey for HistoryKey {
+    type Value = Arc<RwLock<History>>;
+}
+
+impl History {
+    /// Reads the history file if `path` is set, otherwise history only lives in memory.
+    pub fn load(path: &str, capacity: usize) -> History {
+        let mut history: History = if path.is_empty() {
+            History::default()
+        } else {
+            storage::load(Path::new(path), "play history").unwrap_or_default()
+        };
+        history.path = (!path.is_empty()).then(|| PathBuf::from(path));
+        history.capacity = capacity;
+
+        history
+    }
+
+    /// The file to write and its contents, or `None` when history is kept in memory only.
+    fn serialized(&self) -> Option<(PathBuf, String)> {
+        let path = self.path.clone()?;
+
+        Some((path, storage::serialize(self, 4)))
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        match self.serialized() {
+            Some((path, serialized)) => storage::write_async(path, serialized).await,
+            None => Ok(()),
+        }
+    }
+
+    pub fn entries(&self, guild_id: GuildId) -> Vec<HistoryEntry> {
+        self.guilds.get(&guild_id.get())
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn push(&mut self, guild_id: GuildId, entry: HistoryEntry) {
+        let entries = self.guilds.entry(guild_id.get()).or_default();
+        entries.push_front(entry);
+        entries.truncate(self.capacity);
+    }
+}
+
+/// Records every track that actually played once it ends, skipped ones included.
+pub(crate) struct HistoryRecorder {
+    pub guild_id: GuildId,
+    pub history: Arc<RwLock<History>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for HistoryRecorder {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(track_list) = ctx {
+            for (state, handle) in *track_list {
+                // Tracks cleared from the queue end too, without having played.
+                if state.play_time.is_zero() {
+                    continue;
+                }
+
+                // Soundboard clips are not part of the history, and restarted tracks are recorded
+                // once, by the handle that plays them to the end.
+                let info = match handle.typemap().read().await.get::<TrackInfoKey>() {
+                    Some(info) if !info.replaced => info.clone(),
+                    _ => continue,
+                };
+
+                let mut history = self.history.write().await;
+                history.push(self.guild_id, HistoryEntry::new(info));
+                if !std::mem::replace(&mut history.save_scheduled, true) {
+                    tokio::spawn(save_later(self.history.clone()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Writes the history file after `SAVE_DELAY`, off the async runtime.
+async fn save_later(history: Arc<RwLock<History>>) {
+    tokio::time::sleep(SAVE_DELAY).await;
+
+    let serialized = {
+        let mut history = history.write().await;
+        history.save_scheduled = false;
+        history.serialized()
+    };
+    let (path, serialized) = match serialized {
+        Some(serialized) => serialized,
+        None => return,
+    };
+
+    if let Err(error) = storage::write_async(path, serialized).await {
+        println!("Failed to save play history: {}", error);
+    }
+}
@@ -1,9 +1,15 @@
 use std::collections::HashMap;
-use serenity::framework::standard::{CommandError, DispatchError};
+use serenity::framework::standard::{CommandError, DispatchError, Reason};
 use serenity::framework::standard::macros::hook;
 use serenity::model::channel::Message;
 use serenity::prelude::*;
 
+use crate::commands::snapshot_guild;
+
+/// Commands that change what a guild has queued, or where in it playback is.
+const QUEUE_COMMANDS: &[&str] = &["play", "playattachment", "playnext", "search", "replay", "load", "playfile",
+    "skip", "skipto", "stop", "reset_queue", "remove", "move", "shuffle", "seek", "loop", "filter"];
+
 pub(crate) struct CommandCounter;
 
 impl TypeMapKey for CommandCounter {
@@ -24,11 +30,16 @@ pub(crate) async fn before(ctx: &Context, msg: &Message, command_name: &str) ->
 }
 
 #[hook]
-pub(crate) async fn after(_ctx: &Context, _msg: &Message, command_name: &str, command_result: Result<(), CommandError>) {
+pub(crate) async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: Result<(), CommandError>) {
     match command_result {
         Ok(()) => println!("Processed command '{}'", command_name),
         Err(why) => println!("Command '{}' returned error {:?}", command_name, why),
     }
+
+    // Commands are what changes a queue besides tracks ending, so snapshot it after those that do.
+    if let Some(guild_id) = msg.guild_id.filter(|_| QUEUE_COMMANDS.contains(&command_name)) {
+        snapshot_guild(ctx, guild_id).await;
+    }
 }
 
 #[hook]
@@ -49,12 +60,18 @@ pub(crate) async fn delay_action(ctx: &Context, msg: &Message) {
 
 #[hook]
 pub(crate) async fn dispatch_error(ctx: &Context, msg: &Message,error: DispatchError, _command_name: &str) {
-    if let DispatchError::Ratelimited(info) = error {
-        if info.is_first_try {
-            let _ = msg
-                .channel_id
-                .say(&ctx.http, &format!("Try this again in {} seconds.", info.as_secs()))
-                .await;
-        }
+    match error {
+        DispatchError::Ratelimited(info) => {
+            if info.is_first_try {
+                let _ = msg
+                    .channel_id
+                    .say(&ctx.http, &format!("Try this again in {} seconds.", info.as_secs()))
+                    .await;
+            }
+        },
+        DispatchError::CheckFailed(_, Reason::User(reason)) => {
+            let _ = msg.channel_id.say(&ctx.http, reason).await;
+        },
+        _ => {},
     }
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use songbird::input::AuxMetadata;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::Hint;
+
+/// Extensions of the formats symphonia is built with in `Cargo.toml`.
+pub(crate) const SUPPORTED_EXTENSIONS: [&str; 5] = ["mp3", "wav", "m4a", "mp4", "aac"];
+
+pub(crate) struct LibraryEntry {
+    pub path: PathBuf,
+    pub metadata: AuxMetadata,
+}
+
+impl LibraryEntry {
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let metadata = &self.metadata;
+        let file_name = self.path.file_name().and_then(|name| name.to_str());
+
+        [metadata.title.as_deref(), metadata.artist.as_deref(), metadata.album.as_deref(), file_name]
+            .into_iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
+/// Lists every playable file below `dir`, sorted by path. Blocking, run it off the async runtime.
+pub(crate) fn scan(dir: &Path) -> Vec<LibraryEntry> {
+    let mut paths = Vec::new();
+    collect_files(dir, &mut paths);
+    paths.sort();
+
+    paths.into_iter()
+        .filter_map(|path| {
+            let metadata = probe_file(&path)?;
+            Some(LibraryEntry { path, metadata })
+        })
+        .collect()
+}
+
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            println!("Failed to read library directory {:?}: {}", dir, error);
+            return;
+        },
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, paths);
+        } else if path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| SUPPORTED_EXTENSIONS.contains(&extension.to_lowercase().as_str())) {
+            paths.push(path);
+        }
+    }
+}
+
+pub(crate) fn probe_file(path: &Path) -> Option<AuxMetadata> {
+    let file = std::fs::File::open(path).ok()?;
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    let mut metadata = probe(Box::new(file), extension)?;
+    if metadata.title.is_none() {
+        metadata.title = path.file_stem().and_then(|stem| stem.to_str()).map(String::from);
+    }
+
+    Some(metadata)
+}
+
+/// Reads tags and duration through symphonia. `None` means the format is not supported.
+pub(crate) fn probe(source: Box<dyn MediaSource>, extension: Option<&str>) -> Option<AuxMetadata> {
+    let stream = MediaSourceStream::new(source, Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    let mut tags: Vec<Tag> = Vec::new();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|log| log.current()) {
+        tags.extend_from_slice(revision.tags());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend_from_slice(revision.tags());
+    }
+
+    let mut metadata = AuxMetadata::default();
+    for tag in tags {
+        let value = Some(tag.value.to_string());
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => metadata.title = value,
+            Some(StandardTagKey::Artist) => metadata.artist = value,
+            Some(StandardTagKey::Album) => metadata.album = value,
+            _ => {},
+        }
+    }
+
+    let track = probed.format.default_track()?;
+    let params = &track.codec_params;
+    metadata.channels = params.channels.map(|channels| channels.count() as u8);
+    metadata.sample_rate = params.sample_rate;
+    if let (Some(frames), Some(time_base)) = (params.n_frames, params.time_base) {
+        let time = time_base.calc_time(frames);
+        metadata.duration = Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac));
+    }
+
+    Some(metadata)
+}
@@ -1,6 +1,6 @@
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 
 use chrono::offset::Utc;
@@ -8,21 +8,44 @@ use reqwest::Client as HttpClient;
 use serenity::{async_trait, builder, client::{Client, Context, EventHandler}, framework::standard::StandardFramework, model::gateway::Ready, prelude::*};
 use serenity::all::standard::Configuration;
 use serenity::http::Http;
-use serenity::model::id::ChannelId;
+use serenity::model::application::Interaction;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::voice::VoiceState;
 use songbird::SerenityInit;
+use songbird::serenity::SongbirdKey;
 
-use config::Config;
+use config::{Config, ConfigKey};
 
+use crate::cache::{AudioCache, AudioCacheKey};
 use crate::commands::*;
+use crate::history::{History, HistoryKey};
 use crate::hooks::*;
+use crate::playlists::{Playlists, PlaylistsKey};
+use crate::settings::{Settings, SettingsKey};
+use crate::snapshots::{save_all_snapshots, Snapshots, SnapshotsKey};
+use crate::state::GuildStateKey;
+use crate::spotify::SpotifyKey;
 
+pub mod cache;
 pub mod config;
 pub mod commands;
+pub mod filters;
+pub mod history;
 pub mod hooks;
+pub mod library;
+pub mod playlists;
+pub mod settings;
+pub mod snapshots;
+pub mod soundboard;
+pub mod spotify;
+pub mod state;
+pub mod storage;
 pub mod utils;
+pub mod youtube;
 
 struct Handler {
     is_loop_running: AtomicBool,
+    queues_restored: AtomicBool,
 }
 
 #[async_trait]
@@ -55,6 +78,38 @@ impl EventHandler for Handler {
     async fn ready(&self, _: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
     }
+
+    async fn cache_ready(&self, ctx: Context, _guilds: Vec<GuildId>) {
+        // Only once per process, not again when the shard reconnects.
+        if !self.queues_restored.swap(true, Ordering::Relaxed) {
+            restore_queues(&ctx).await;
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        // Other buttons are handled by the collectors of the commands that posted them.
+        if let Interaction::Component(component) = interaction {
+            if component.data.custom_id.starts_with("np_") {
+                handle_now_playing_button(&ctx, &component).await;
+            }
+        }
+    }
+
+    async fn voice_state_update(&self, ctx: Context, _old: Option<VoiceState>, new: VoiceState) {
+        let guild_id = match new.guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+
+        // Clean up after being disconnected by someone else, or by `leave`.
+        if new.user_id == ctx.cache.current_user().id && new.channel_id.is_none() {
+            leave_voice(&ctx, guild_id).await;
+            return;
+        }
+
+        // Members joining or leaving, and the bot being moved, may leave it alone or end that.
+        update_alone_timer(&ctx, guild_id).await;
+    }
 }
 
 // async fn log_system_load(ctx: Arc<Context>) {
@@ -95,21 +150,19 @@ async fn main() {
     let _ = Config::new().save();
     let config = Config::load().unwrap();
 
-    // {
-    //     let creds = Credentials {
-    //         id: config.spotify_client_id().to_string(),
-    //         secret: Some(config.spotify_client_secret().to_string()),
-    //     };
-    //
-    //     let spotify = ClientCredsSpotify::new(creds);
-    //
-    //     spotify.request_token().await.unwrap();
-    //
-    //     let birdy_uri = AlbumId::from_uri("spotify:album:0sNOF9WDwhWunNAHPD3Baj").unwrap();
-    //     let albums = spotify.album(birdy_uri).await;
-    //
-    //     println!("Response: {albums:#?}");
-    // }
+    let spotify = match spotify::connect(&config).await {
+        Ok(spotify) => Some(spotify),
+        Err(why) => {
+            println!("Spotify links are disabled, could not get a token: {:?}", why);
+            None
+        },
+    };
+
+    let settings = Settings::load(config.settings_path());
+    let history = Arc::new(RwLock::new(History::load(config.history_path(), config.history_size())));
+    let playlists = Playlists::load(config.playlists_path());
+    let snapshots = Arc::new(RwLock::new(Snapshots::load(config.queues_path())));
+    let cache = AudioCache::load(config.cache_dir(), config.cache_size());
 
     let http = Http::new(&config.token());
 
@@ -153,19 +206,67 @@ async fn main() {
     let mut client = Client::builder(&config.token(), intents)
         .event_handler(Handler {
             is_loop_running: AtomicBool::new(false),
+            queues_restored: AtomicBool::new(false),
         })
         .framework(framework)
         .register_songbird()
         .type_map_insert::<HttpKey>(HttpClient::new())
         .type_map_insert::<CommandCounter>(HashMap::default())
+        .type_map_insert::<ConfigKey>(Arc::new(config))
+        .type_map_insert::<SettingsKey>(Arc::new(RwLock::new(settings)))
+        .type_map_insert::<GuildStateKey>(Arc::new(RwLock::new(HashMap::new())))
+        .type_map_insert::<HistoryKey>(history.clone())
+        .type_map_insert::<PlaylistsKey>(Arc::new(RwLock::new(playlists)))
+        .type_map_insert::<SnapshotsKey>(snapshots.clone())
+        .type_map_insert::<AudioCacheKey>(Arc::new(RwLock::new(cache)))
         .await
         .expect("Error creating client!");
     {
         let mut data = client.data.write().await;
         //data.insert::<ShardManagerContainer>(Arc::clone(&client.shard_manager));
+        if let Some(spotify) = spotify {
+            data.insert::<SpotifyKey>(spotify);
+        }
     }
 
+    let shard_manager = client.shard_manager.clone();
+    let data = client.data.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+
+        // Save where every queue is before the voice connections go away.
+        let (manager, states) = {
+            let data = data.read().await;
+            (data.get::<SongbirdKey>().cloned(), data.get::<GuildStateKey>().cloned())
+        };
+        if let (Some(manager), Some(states)) = (manager, states) {
+            save_all_snapshots(&manager, &states, &snapshots).await;
+        }
+        // History is written with a delay, so the last tracks may not be on disk yet.
+        if let Err(error) = history.read().await.save().await {
+            println!("Failed to save play history: {}", error);
+        }
+
+        shard_manager.shutdown_all().await;
+    });
+
     if let Err(error) = client.start().await {
         println!("Client error: {}.", error);
     }
-}
\ No newline at end of file
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM where there is one, as sent by `docker stop`.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM.");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::{RwLock, TypeMapKey};
+use songbird::input::AuxMetadata;
+
+use crate::commands::TrackSource;
+use crate::storage;
+
+/// Enough of a track to show it and create its input again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SavedTrack {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub url: Option<String>,
+    pub thumbnail: Option<String>,
+    pub duration: Option<u64>,
+    pub source: TrackSource,
+}
+
+impl SavedTrack {
+    pub fn new(metadata: AuxMetadata, source: TrackSource) -> Self {
+        SavedTrack {
+            title: metadata.title.or(metadata.track),
+            artist: metadata.artist,
+            url: metadata.source_url,
+            thumbnail: metadata.thumbnail,
+            duration: metadata.duration.map(|duration| duration.as_secs()),
+            source,
+        }
+    }
+
+    pub fn metadata(&self) -> AuxMetadata {
+        AuxMetadata {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            source_url: self.url.clone(),
+            thumbnail: self.thumbnail.clone(),
+            duration: self.duration.map(Duration::from_secs),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Scope {
+    /// Owned by one person and usable on every server.
+    User(UserId),
+    /// Shared by everyone on a server.
+    Guild(GuildId),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Playlist {
+    pub name: String,
+    pub created_by: UserId,
+    pub tracks: Vec<SavedTrack>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Playlists {
+    users: HashMap<u64, Vec<Playlist>>,
+    guilds: HashMap<u64, Vec<Playlist>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+pub(crate) struct PlaylistsKey;
+
+impl TypeMapKey for PlaylistsKey {
+    type Value = Arc<RwLock<Playlists>>;
+}
+
+impl Playlists {
+    pub fn load(path: &str) -> Playlists {
+        let mut playlists: Playlists = storage::load(Path::new(path), "playlists")
+            .unwrap_or_default();
+        playlists.path = PathBuf::from(path);
+
+        playlists
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        let serialized = storage::serialize(self, 5);
+
+        storage::write_async(self.path.clone(), serialized).await
+    }
+
+    pub fn list(&self, scope: Scope) -> &[Playlist] {
+        let playlists = match scope {
+            Scope::User(user_id) => self.users.get(&user_id.get()),
+            Scope::Guild(guild_id) => self.guilds.get(&guild_id.get()),
+        };
+
+        playlists.map_or(&[], Vec::as_slice)
+    }
+
+    /// The scope's playlists, without adding an empty list for scopes that have none.
+    fn existing_mut(&mut self, scope: Scope) -> Option<&mut Vec<Playlist>> {
+        match scope {
+            Scope::User(user_id) => self.users.get_mut(&user_id.get()),
+            Scope::Guild(guild_id) => self.guilds.get_mut(&guild_id.get()),
+        }
+    }
+
+    fn list_mut(&mut self, scope: Scope) -> &mut Vec<Playlist> {
+        match scope {
+            Scope::User(user_id) => self.users.entry(user_id.get()).or_default(),
+            Scope::Guild(guild_id) => self.guilds.entry(guild_id.get()).or_default(),
+        }
+    }
+
+    /// Looks `name` up among the user's own playlists first, then the server's.
+    pub fn find(&self, user_id: UserId, guild_id: Option<GuildId>, name: &str) -> Option<(Scope, &Playlist)> {
+        let mut scopes = vec![Scope::User(user_id)];
+        scopes.extend(guild_id.map(Scope::Guild));
+
+        scopes.into_iter().find_map(|scope| {
+            self.list(scope).iter()
+                .find(|playlist| playlist.name.eq_ignore_ascii_case(name))
+                .map(|playlist| (scope, playlist))
+        })
+    }
+
+    pub fn get_mut(&mut self, scope: Scope, name: &str) -> Option<&mut Playlist> {
+        self.existing_mut(scope)?.iter_mut().find(|playlist| playlist.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns `false` when the scope already has a playlist with that name.
+    pub fn create(&mut self, scope: Scope, name: &str, created_by: UserId) -> bool {
+        if self.list(scope).iter().any(|playlist| playlist.name.eq_ignore_ascii_case(name)) {
+            return false;
+        }
+
+        self.list_mut(scope).push(Playlist { name: name.to_string(), created_by, tracks: Vec::new() });
+        true
+    }
+
+    pub fn delete(&mut self, scope: Scope, name: &str) -> Option<Playlist> {
+        let playlists = self.existing_mut(scope)?;
+        let index = playlists.iter().position(|playlist| playlist.name.eq_ignore_ascii_case(name))?;
+
+        Some(playlists.remove(index))
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, RoleId};
+use serenity::prelude::{RwLock, TypeMapKey};
+
+use crate::storage;
+
+/// Playback preferences a guild keeps across restarts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Percentage applied to every track, 100 being the source level.
+    pub volume: u16,
+    /// Ceiling set by the guild's admins for `volume`.
+    pub max_volume: u16,
+    /// Keeps the bot connected while idle or alone.
+    pub always_on: bool,
+    /// Role allowed to run destructive commands besides admins. Everyone may while unset.
+    pub dj_role: Option<RoleId>,
+    /// Longest track accepted on enqueue, in seconds.
+    pub max_track_length: Option<u64>,
+    pub max_user_tracks: Option<usize>,
+    pub max_queue_length: Option<usize>,
+    /// Interleaves requesters in the queue instead of keeping it first-come, first-served.
+    pub round_robin: bool,
+    /// Rejoins and resumes the queue after a restart without asking first.
+    pub auto_resume: bool,
+    /// Queues related tracks once the queue runs out.
+    pub autoplay: bool,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        GuildSettings {
+            volume: 100,
+            max_volume: 200,
+            always_on: false,
+            dj_role: None,
+            max_track_length: None,
+            max_user_tracks: None,
+            max_queue_length: None,
+            round_robin: false,
+            auto_resume: false,
+            autoplay: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Settings {
+    guilds: HashMap<u64, GuildSettings>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+pub(crate) struct SettingsKey;
+
+impl TypeMapKey for SettingsKey {
+    type Value = Arc<RwLock<Settings>>;
+}
+
+impl Settings {
+    /// Reads the settings file, starting empty when there is none yet.
+    pub fn load(path: &str) -> Settings {
+        let mut settings: Settings = storage::load(Path::new(path), "guild settings")
+            .unwrap_or_default();
+        settings.path = PathBuf::from(path);
+
+        settings
+    }
+
+    /// Writes the settings off the async runtime. Callers keep holding the lock until it is done
+    /// so that saves land in the order the changes were made.
+    pub async fn save(&self) -> std::io::Result<()> {
+        let serialized = storage::serialize(self, 3);
+
+        storage::write_async(self.path.clone(), serialized).await
+    }
+
+    pub fn guild(&self, guild_id: GuildId) -> GuildSettings {
+        self.guilds.get(&guild_id.get())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn guild_mut(&mut self, guild_id: GuildId) -> &mut GuildSettings {
+        self.guilds.entry(guild_id.get()).or_default()
+    }
+}
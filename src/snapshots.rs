@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::prelude::{RwLock, TypeMapKey};
+use songbird::Songbird;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler};
+
+use crate::commands::{get_track_info, track_position};
+use crate::filters::Filter;
+use crate::playlists::SavedTrack;
+use crate::state::{GuildStates, LoopMode};
+use crate::storage;
+
+/// How long queue changes are collected before the snapshots are taken and written, so that a burst
+/// of commands or tracks skipped in a row cost a single pass over the queue.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// A queued track along with who asked for it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SnapshotTrack {
+    pub track: SavedTrack,
+    pub requester: Option<UserId>,
+    pub channel_id: Option<ChannelId>,
+}
+
+/// Everything needed to pick a guild's queue back up after a restart.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct QueueSnapshot {
+    pub voice_channel: ChannelId,
+    pub text_channel: Option<ChannelId>,
+    /// How far into the first track playback was, in seconds.
+    pub position: u64,
+    pub loop_mode: LoopMode,
+    pub filter: Filter,
+    pub tracks: Vec<SnapshotTrack>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Snapshots {
+    guilds: HashMap<u64, QueueSnapshot>,
+    #[serde(skip)]
+    path: PathBuf,
+    /// Guilds whose queue changed since the last scheduled save.
+    #[serde(skip)]
+    pending: HashSet<u64>,
+}
+
+pub(crate) struct SnapshotsKey;
+
+impl TypeMapKey for SnapshotsKey {
+    type Value = Arc<RwLock<Snapshots>>;
+}
+
+impl Snapshots {
+    pub fn load(path: &str) -> Snapshots {
+        let mut snapshots: Snapshots = storage::load(Path::new(path), "queue snapshots")
+            .unwrap_or_default();
+        snapshots.path = PathBuf::from(path);
+
+        snapshots
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        let serialized = storage::serialize(self, 5);
+
+        storage::write_async(self.path.clone(), serialized).await
+    }
+
+    pub fn all(&self) -> Vec<(GuildId, QueueSnapshot)> {
+        self.guilds.iter()
+            .map(|(guild_id, snapshot)| (GuildId::new(*guild_id), snapshot.clone()))
+            .collect()
+    }
+
+    /// Replaces the guild's snapshot, or forgets it for `None`. Returns whether anything changed on disk.
+    pub fn set(&mut self, guild_id: GuildId, snapshot: Option<QueueSnapshot>) -> bool {
+        match snapshot {
+            Some(snapshot) => {
+                self.guilds.insert(guild_id.get(), snapshot);
+                true
+            },
+            None => self.guilds.remove(&guild_id.get()).is_some(),
+        }
+    }
+
+    /// Forgets the guild's snapshot if it still is `expected`. Returns whether anything changed on disk.
+    pub fn forget_if(&mut self, guild_id: GuildId, expected: &QueueSnapshot) -> bool {
+        if self.guilds.get(&guild_id.get()) != Some(expected) {
+            return false;
+        }
+
+        self.set(guild_id, None)
+    }
+}
+
+/// What the guild's call is playing, `Some(None)` once its queue is empty.
+///
+/// Guilds without a call give `None`, so a snapshot waiting to be resumed survives.
+async fn take_snapshot(manager: &Songbird, states: &GuildStates, guild_id: GuildId) -> Option<Option<QueueSnapshot>> {
+    let handler_lock = manager.get(guild_id)?;
+
+    let (voice_channel, queued) = {
+        let handler = handler_lock.lock().await;
+        (handler.current_channel(), handler.queue().current_queue())
+    };
+
+    let snapshot = match voice_channel {
+        Some(voice_channel) if !queued.is_empty() => {
+            let mut position = 0;
+            let mut tracks = Vec::with_capacity(queued.len());
+            for (index, track) in queued.iter().enumerate() {
+                let info = get_track_info(track).await;
+                let source = match info.source.clone() {
+                    Some(source) => source,
+                    None => continue,
+                };
+
+                if index == 0 {
+                    position = track_position(track, &info).await.as_secs();
+                }
+                tracks.push(SnapshotTrack {
+                    track: SavedTrack::new(info.metadata, source),
+                    requester: info.requester,
+                    channel_id: info.channel_id,
+                });
+            }
+
+            let states = states.read().await;
+            let state = states.get(&guild_id);
+            (!tracks.is_empty()).then(|| QueueSnapshot {
+                voice_channel: ChannelId::new(voice_channel.0.get()),
+                text_channel: state.and_then(|state| state.text_channel),
+                position,
+                loop_mode: state.map(|state| state.loop_mode).unwrap_or_default(),
+                filter: state.map(|state| state.filter).unwrap_or_default(),
+                tracks,
+            })
+        },
+        _ => None,
+    };
+
+    Some(snapshot)
+}
+
+/// Takes the snapshots of `guild_ids` and writes them if any changed.
+async fn save_snapshots(manager: &Songbird, states: &GuildStates, snapshots: &RwLock<Snapshots>, guild_ids: Vec<GuildId>) {
+    let mut taken = Vec::with_capacity(guild_ids.len());
+    for guild_id in guild_ids {
+        if let Some(snapshot) = take_snapshot(manager, states, guild_id).await {
+            taken.push((guild_id, snapshot));
+        }
+    }
+
+    let mut snapshots = snapshots.write().await;
+    let mut changed = false;
+    for (guild_id, snapshot) in taken {
+        // The bot may have left while the queue was read, and forgotten the snapshot with it.
+        if manager.get(guild_id).is_some() {
+            changed |= snapshots.set(guild_id, snapshot);
+        }
+    }
+    if changed {
+        if let Err(error) = snapshots.save().await {
+            println!("Failed to save queue snapshots: {}", error);
+        }
+    }
+}
+
+/// Snapshots the guild's queue after `SAVE_DELAY`, along with every other guild changed until then.
+pub(crate) async fn schedule_snapshot(manager: &Arc<Songbird>, states: &GuildStates, snapshots: &Arc<RwLock<Snapshots>>, guild_id: GuildId) {
+    let mut pending = snapshots.write().await;
+    let scheduled = !pending.pending.is_empty();
+    pending.pending.insert(guild_id.get());
+    if !scheduled {
+        tokio::spawn(save_later(manager.clone(), states.clone(), snapshots.clone()));
+    }
+}
+
+async fn save_later(manager: Arc<Songbird>, states: GuildStates, snapshots: Arc<RwLock<Snapshots>>) {
+    tokio::time::sleep(SAVE_DELAY).await;
+
+    let guild_ids = std::mem::take(&mut snapshots.write().await.pending);
+    let guild_ids = guild_ids.into_iter().map(GuildId::new).collect();
+    save_snapshots(&manager, &states, &snapshots, guild_ids).await;
+}
+
+/// Saves every guild that is playing, with the current offsets. Run right before shutting down.
+pub(crate) async fn save_all_snapshots(manager: &Songbird, states: &GuildStates, snapshots: &RwLock<Snapshots>) {
+    let guild_ids: Vec<GuildId> = manager.iter()
+        .map(|(guild_id, _)| GuildId::new(guild_id.0.get()))
+        .collect();
+
+    save_snapshots(manager, states, snapshots, guild_ids).await;
+}
+
+/// Schedules a snapshot whenever a track starts, which covers the queue moving on by itself.
+pub(crate) struct QueueSnapshotter {
+    pub guild_id: GuildId,
+    pub manager: Arc<Songbird>,
+    pub states: GuildStates,
+    pub snapshots: Arc<RwLock<Snapshots>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for QueueSnapshotter {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        schedule_snapshot(&self.manager, &self.states, &self.snapshots, self.guild_id).await;
+        None
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serenity::async_trait;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler};
+use songbird::tracks::TrackHandle;
+
+use crate::library::{self, SUPPORTED_EXTENSIONS};
+
+/// Names taken by the `sb` subcommands.
+const RESERVED_NAMES: [&str; 4] = ["add", "list", "rename", "delete"];
+
+pub(crate) struct Clip {
+    pub name: String,
+    pub duration: Option<Duration>,
+}
+
+/// Clip names double as file names, so they are kept to lowercase letters, digits, `-` and `_`.
+pub(crate) fn valid_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && !RESERVED_NAMES.contains(&name)
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| SUPPORTED_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Finds the file of a clip, whatever its extension.
+pub(crate) fn find(dir: &Path, name: &str) -> Option<PathBuf> {
+    std::fs::read_dir(dir).ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| is_supported(path) && path.file_stem().and_then(|stem| stem.to_str()) == Some(name))
+}
+
+/// Lists the clips in `dir`, sorted by name. Blocking, run it off the async runtime.
+pub(crate) fn clips(dir: &Path) -> Vec<Clip> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut clips: Vec<Clip> = entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_supported(path))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?.to_string();
+            let duration = library::probe_file(&path).and_then(|metadata| metadata.duration);
+            Some(Clip { name, duration })
+        })
+        .collect();
+    clips.sort_by(|a, b| a.name.cmp(&b.name));
+
+    clips
+}
+
+/// Resumes the queue's track once a clip that interrupted it is over.
+pub(crate) struct ResumeAfterClip {
+    pub track: TrackHandle,
+}
+
+#[async_trait]
+impl VoiceEventHandler for ResumeAfterClip {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let _ = self.track.play();
+        Some(Event::Cancel)
+    }
+}
@@ -1 +1,233 @@
+use std::collections::HashMap;
 
+use rspotify::{ClientCredsSpotify, ClientResult, Config as SpotifyConfig, Credentials};
+use rspotify::clients::BaseClient;
+use rspotify::model::{AlbumId, ArtistId, Image, PlayableItem, PlaylistId, SimplifiedArtist, TrackId};
+use serenity::futures::TryStreamExt;
+use serenity::prelude::TypeMapKey;
+use songbird::input::AuxMetadata;
+
+use crate::config::Config;
+
+pub(crate) struct SpotifyKey;
+
+impl TypeMapKey for SpotifyKey {
+    type Value = ClientCredsSpotify;
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum SpotifyLink {
+    Track(TrackId<'static>),
+    Album(AlbumId<'static>),
+    Playlist(PlaylistId<'static>),
+}
+
+/// A Spotify track resolved into a query that yt-dlp can search for.
+pub(crate) struct SpotifyTrack {
+    pub query: String,
+    pub metadata: AuxMetadata,
+}
+
+/// Requests a client-credentials token once; rspotify refreshes it on expiry.
+pub(crate) async fn connect(config: &Config) -> ClientResult<ClientCredsSpotify> {
+    let creds = Credentials::new(config.spotify_client_id(), config.spotify_client_secret());
+    let spotify_config = SpotifyConfig {
+        token_refreshing: true,
+        ..Default::default()
+    };
+
+    let spotify = ClientCredsSpotify::with_config(creds, spotify_config);
+    spotify.request_token().await?;
+
+    Ok(spotify)
+}
+
+/// Recognises `open.spotify.com` links and `spotify:` URIs for tracks, albums and playlists.
+pub(crate) fn parse_link(link: &str) -> Option<SpotifyLink> {
+    let (kind, id) = if let Some(uri) = link.strip_prefix("spotify:") {
+        uri.split_once(':')?
+    } else {
+        let path = link
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .strip_prefix("open.spotify.com/")?;
+        let path = path.split(['?', '#']).next()?;
+
+        let mut segments = path.split('/')
+            .filter(|segment| !segment.is_empty() && !segment.starts_with("intl-"));
+
+        (segments.next()?, segments.next()?)
+    };
+
+    let id = id.to_string();
+
+    match kind {
+        "track" => TrackId::from_id(id).ok().map(SpotifyLink::Track),
+        "album" => AlbumId::from_id(id).ok().map(SpotifyLink::Album),
+        "playlist" => PlaylistId::from_id(id).ok().map(SpotifyLink::Playlist),
+        _ => None,
+    }
+}
+
+/// Resolves a link into its tracks, along with the album or playlist name when there is one.
+pub(crate) async fn resolve(
+    spotify: &ClientCredsSpotify,
+    link: &SpotifyLink,
+) -> ClientResult<(Option<String>, Vec<SpotifyTrack>)> {
+    match link {
+        SpotifyLink::Track(id) => {
+            let track = spotify.track(id.as_ref()).await?;
+            let resolved = to_spotify_track(
+                &track.name,
+                &track.artists,
+                Some(&track.album.name),
+                &track.album.images,
+                track.duration,
+                &track.external_urls,
+            );
+
+            Ok((None, vec![resolved]))
+        },
+        SpotifyLink::Album(id) => {
+            let album = spotify.album(id.as_ref()).await?;
+            let tracks = spotify.album_track(id.as_ref()).try_collect::<Vec<_>>().await?;
+
+            let resolved = tracks.iter()
+                .map(|track| to_spotify_track(
+                    &track.name,
+                    &track.artists,
+                    Some(&album.name),
+                    &album.images,
+                    track.duration,
+                    &track.external_urls,
+                ))
+                .collect();
+
+            Ok((Some(album.name), resolved))
+        },
+        SpotifyLink::Playlist(id) => {
+            let playlist = spotify.playlist(id.as_ref(), None, None).await?;
+            let items = spotify.playlist_items(id.as_ref(), None, None).try_collect::<Vec<_>>().await?;
+
+            let resolved = items.iter()
+                .filter_map(|item| match &item.track {
+                    Some(PlayableItem::Track(track)) => Some(to_spotify_track(
+                        &track.name,
+                        &track.artists,
+                        Some(&track.album.name),
+                        &track.album.images,
+                        track.duration,
+                        &track.external_urls,
+                    )),
+                    _ => None,
+                })
+                .collect();
+
+            Ok((Some(playlist.name), resolved))
+        },
+    }
+}
+
+/// Tracks Spotify recommends from up to five seed tracks.
+pub(crate) async fn recommendations(
+    spotify: &ClientCredsSpotify,
+    seeds: Vec<TrackId<'static>>,
+) -> ClientResult<Vec<SpotifyTrack>> {
+    let recommendations = spotify
+        .recommendations([], None::<Vec<ArtistId>>, None::<Vec<&str>>, Some(seeds.into_iter().take(5)), None, Some(20))
+        .await?;
+
+    let tracks = recommendations.tracks.iter()
+        .map(|track| to_spotify_track(
+            &track.name,
+            &track.artists,
+            None,
+            &[],
+            track.duration,
+            &track.external_urls,
+        ))
+        .collect();
+
+    Ok(tracks)
+}
+
+fn to_spotify_track(
+    name: &str,
+    artists: &[SimplifiedArtist],
+    album: Option<&str>,
+    images: &[Image],
+    duration: chrono::Duration,
+    external_urls: &HashMap<String, String>,
+) -> SpotifyTrack {
+    let artist = artists.iter()
+        .map(|artist| artist.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let query = if artist.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} - {}", artist, name)
+    };
+
+    let metadata = AuxMetadata {
+        track: Some(name.to_string()),
+        title: Some(query.clone()),
+        artist: (!artist.is_empty()).then_some(artist),
+        album: album.map(str::to_string),
+        duration: duration.to_std().ok(),
+        source_url: external_urls.get("spotify").cloned(),
+        thumbnail: images.first().map(|image| image.url.clone()),
+        ..Default::default()
+    };
+
+    SpotifyTrack { query, metadata }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "4uLU6hMCjMI75M1A2tKUQC";
+
+    #[test]
+    fn parse_link_reads_urls() {
+        assert_eq!(parse_link(&format!("https://open.spotify.com/track/{}", ID)),
+            Some(SpotifyLink::Track(TrackId::from_id(ID).unwrap())));
+        assert_eq!(parse_link(&format!("https://open.spotify.com/album/{}", ID)),
+            Some(SpotifyLink::Album(AlbumId::from_id(ID).unwrap())));
+        assert_eq!(parse_link(&format!("https://open.spotify.com/playlist/{}", ID)),
+            Some(SpotifyLink::Playlist(PlaylistId::from_id(ID).unwrap())));
+        assert_eq!(parse_link(&format!("open.spotify.com/intl-de/track/{}", ID)),
+            Some(SpotifyLink::Track(TrackId::from_id(ID).unwrap())));
+    }
+
+    #[test]
+    fn parse_link_reads_uris() {
+        assert_eq!(parse_link(&format!("spotify:track:{}", ID)),
+            Some(SpotifyLink::Track(TrackId::from_id(ID).unwrap())));
+        assert_eq!(parse_link(&format!("spotify:album:{}", ID)),
+            Some(SpotifyLink::Album(AlbumId::from_id(ID).unwrap())));
+        assert_eq!(parse_link(&format!("spotify:playlist:{}", ID)),
+            Some(SpotifyLink::Playlist(PlaylistId::from_id(ID).unwrap())));
+    }
+
+    #[test]
+    fn parse_link_ignores_share_suffixes() {
+        assert_eq!(parse_link(&format!("https://open.spotify.com/track/{}?si=abc123", ID)),
+            Some(SpotifyLink::Track(TrackId::from_id(ID).unwrap())));
+        assert_eq!(parse_link(&format!("https://open.spotify.com/playlist/{}?si=abc123&pi=x#top", ID)),
+            Some(SpotifyLink::Playlist(PlaylistId::from_id(ID).unwrap())));
+    }
+
+    #[test]
+    fn parse_link_rejects_invalid_input() {
+        assert_eq!(parse_link(""), None);
+        assert_eq!(parse_link("never gonna give you up"), None);
+        assert_eq!(parse_link(&format!("https://open.spotify.com/artist/{}", ID)), None);
+        assert_eq!(parse_link(&format!("https://example.com/track/{}", ID)), None);
+        assert_eq!(parse_link("https://open.spotify.com/track/"), None);
+        assert_eq!(parse_link("https://open.spotify.com/track/not-an-id!"), None);
+        assert_eq!(parse_link("spotify:track"), None);
+    }
+}
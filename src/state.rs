@@ -0,0 +1,65 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::prelude::{RwLock, TypeMapKey};
+use songbird::tracks::TrackHandle;
+use tokio::task::AbortHandle;
+
+use crate::filters::Filter;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum LoopMode {
+    #[default]
+    Off,
+    Track,
+    Queue,
+}
+
+impl LoopMode {
+    /// The mode after this one, as cycled through by the now-playing controller.
+    pub fn next(self) -> LoopMode {
+        match self {
+            LoopMode::Off => LoopMode::Track,
+            LoopMode::Track => LoopMode::Queue,
+            LoopMode::Queue => LoopMode::Off,
+        }
+    }
+}
+
+impl fmt::Display for LoopMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopMode::Off => write!(f, "off"),
+            LoopMode::Track => write!(f, "track"),
+            LoopMode::Queue => write!(f, "queue"),
+        }
+    }
+}
+
+/// Playback state of a guild that only lives as long as the bot runs.
+#[derive(Debug, Default)]
+pub struct GuildState {
+    pub loop_mode: LoopMode,
+    /// Where the last voice command came from, for notices that are not replies.
+    pub text_channel: Option<ChannelId>,
+    /// Track the skip votes were cast for; votes are dropped once another one plays.
+    pub vote_track: Option<TrackHandle>,
+    pub skip_votes: HashSet<UserId>,
+    /// Applied to every track enqueued from now on.
+    pub filter: Filter,
+    /// The now-playing controller, edited in place as tracks change.
+    pub now_playing: Option<(ChannelId, MessageId)>,
+    /// Counts down to leaving while nobody else is in the bot's voice channel.
+    pub alone_timer: Option<AbortHandle>,
+}
+
+pub(crate) type GuildStates = Arc<RwLock<HashMap<GuildId, GuildState>>>;
+
+pub(crate) struct GuildStateKey;
+
+impl TypeMapKey for GuildStateKey {
+    type Value = GuildStates;
+}
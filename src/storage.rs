@@ -0,0 +1,101 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use ron::{de, ser};
+use ron::ser::PrettyConfig;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Reads a RON file, `None` when there is nothing usable in it yet.
+///
+/// A file that no longer parses is moved aside to `<path>.bak` first, so that the next save can't
+/// overwrite whatever it still held. Refuses to start when even that fails.
+pub(crate) fn load<T: DeserializeOwned>(path: &Path, what: &str) -> Option<T> {
+    let file = File::open(path).ok()?;
+    match de::from_reader(file) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            let backup = backup_path(path);
+            if let Err(why) = std::fs::rename(path, &backup) {
+                panic!("Failed to load {} and could not move {} aside: {}", what, path.display(), why);
+            }
+            println!("Failed to load {}, moved it to {} and starting over: {}", what, backup.display(), error);
+            None
+        },
+    }
+}
+
+pub(crate) fn serialize<T: Serialize>(value: &T, depth_limit: usize) -> String {
+    let pretty = PrettyConfig::new()
+        .depth_limit(depth_limit);
+
+    ser::to_string_pretty(value, pretty)
+        .expect("Serialization failed!")
+}
+
+/// Replaces `path` with `contents` by writing a temporary file next to it and renaming that into
+/// place, so a crash halfway through leaves the previous version intact.
+pub(crate) fn write(path: &Path, contents: &str) -> std::io::Result<()> {
+    let temp = sibling_path(path, ".tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    std::fs::rename(&temp, path)
+}
+
+/// `write` on the blocking thread pool, for callers on the async runtime.
+pub(crate) async fn write_async(path: PathBuf, contents: String) -> std::io::Result<()> {
+    tokio::task::spawn_blocking(move || write(&path, &contents))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".bak")
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("storage-{}-{}.ron", name, std::process::id()))
+    }
+
+    #[test]
+    fn write_replaces_file_and_leaves_no_temp() {
+        let path = temp_path("write");
+        write(&path, "(a: 1)").unwrap();
+        write(&path, "(a: 2)").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "(a: 2)");
+        assert!(!sibling_path(&path, ".tmp").exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_moves_unparsable_file_aside() {
+        let path = temp_path("load");
+        std::fs::write(&path, "not ron {").unwrap();
+
+        assert_eq!(load::<Vec<u64>>(&path, "test data"), None);
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_to_string(backup_path(&path)).unwrap(), "not ron {");
+        std::fs::remove_file(backup_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn load_missing_file() {
+        assert_eq!(load::<Vec<u64>>(&temp_path("missing"), "test data"), None);
+    }
+}
@@ -7,4 +7,73 @@ pub fn to_time(secs: u64) -> String {
         return format!("{:0>2}:{:0>2}", min, sec);
     }
     return format!("{:0>2}:{:0>2}:{:0>2}", hrs, min, sec);
-}
\ No newline at end of file
+}
+
+/// Inverse of `to_time`: accepts `90`, `1:30` or `01:02:03`.
+pub fn parse_time(time: &str) -> Option<u64> {
+    let parts = time.split(':')
+        .map(|part| part.trim().parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    match parts.as_slice() {
+        [secs] => Some(*secs),
+        [min, sec] if *sec < 60 => min.checked_mul(60)?.checked_add(*sec),
+        [hrs, min, sec] if *min < 60 && *sec < 60 => hrs.checked_mul(60)?.checked_add(*min)?.checked_mul(60)?.checked_add(*sec),
+        _ => None,
+    }
+}
+
+pub fn to_size(bytes: u64) -> String {
+    if bytes < 1024 * 1024 {
+        return format!("{:.1} KB", bytes as f64 / 1024.0);
+    }
+    return format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0);
+}
+
+pub fn progress_bar(elapsed: u64, total: u64, width: usize) -> String {
+    let filled = (elapsed.min(total) * width as u64)
+        .checked_div(total)
+        .unwrap_or(0) as usize;
+
+    let mut bar = "▬".repeat(filled);
+    bar.push('🔘');
+    bar.push_str(&"▬".repeat(width - filled));
+
+    return bar;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_reads_every_format() {
+        assert_eq!(parse_time("90"), Some(90));
+        assert_eq!(parse_time("1:30"), Some(90));
+        assert_eq!(parse_time("01:02:03"), Some(3723));
+    }
+
+    #[test]
+    fn parse_time_rejects_invalid_input() {
+        assert_eq!(parse_time("1:60"), None);
+        assert_eq!(parse_time("1:60:00"), None);
+        assert_eq!(parse_time("1:00:60"), None);
+        assert_eq!(parse_time("1:2:3:4"), None);
+        assert_eq!(parse_time("1:x"), None);
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("-5"), None);
+    }
+
+    #[test]
+    fn parse_time_rejects_overflowing_input() {
+        assert_eq!(parse_time("999999999999999999:00"), None);
+        assert_eq!(parse_time("9999999999999999:00:00"), None);
+    }
+
+    #[test]
+    fn parse_time_inverts_to_time() {
+        for secs in [0, 5, 59, 60, 90, 3599, 3600, 3723, 86399, 360000] {
+            assert_eq!(parse_time(&to_time(secs)), Some(secs));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use songbird::input::AuxMetadata;
+use youtube_dl::{SingleVideo, YoutubeDl, YoutubeDlOutput};
+
+/// A single video listed by a flattened playlist.
+pub(crate) struct PlaylistEntry {
+    pub url: String,
+    pub metadata: AuxMetadata,
+}
+
+fn strip_host_prefix(url: &str) -> &str {
+    url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .trim_start_matches("m.")
+        .trim_start_matches("music.")
+}
+
+/// The playlist a link points to, as a `playlist?list=` URL. Videos shared from a playlist count
+/// too, except from the `RD` mixes YouTube generates around a video.
+pub(crate) fn playlist_url(url: &str) -> Option<String> {
+    let url = strip_host_prefix(url);
+    let (path, query) = url.split_once('?')?;
+    let id = query.split(['&', '#']).find_map(|param| param.strip_prefix("list="))?;
+
+    let shared_video = path == "youtube.com/watch" || path.starts_with("youtu.be/");
+    if id.is_empty() || !(path == "youtube.com/playlist" || (shared_video && !id.starts_with("RD"))) {
+        return None;
+    }
+
+    Some(format!("https://www.youtube.com/playlist?list={}", id))
+}
+
+/// Reads the `t=` offset of a YouTube link, written as `90`, `90s` or `1h2m30s`.
+pub(crate) fn start_time(url: &str) -> Option<Duration> {
+    let url = strip_host_prefix(url);
+    if !url.starts_with("youtube.com/") && !url.starts_with("youtu.be/") {
+        return None;
+    }
+
+    let (_, query) = url.split_once('?')?;
+    let value = query.split(['&', '#'])
+        .find_map(|param| param.strip_prefix("t="))?;
+
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let multiplier = match c {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        secs = secs.checked_add(number.parse::<u64>().ok()?.checked_mul(multiplier)?)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        secs = secs.checked_add(number.parse::<u64>().ok()?)?;
+    }
+
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Reads the video ID of a `watch?v=` or `youtu.be/` link.
+pub(crate) fn video_id(url: &str) -> Option<&str> {
+    let url = strip_host_prefix(url);
+
+    let id = match url.strip_prefix("youtu.be/") {
+        Some(path) => path.split(['?', '&', '#']).next()?,
+        None => {
+            let (_, query) = url.strip_prefix("youtube.com/watch")?.split_once('?')?;
+            query.split(['&', '#']).find_map(|param| param.strip_prefix("v="))?
+        },
+    };
+
+    (!id.is_empty()).then_some(id)
+}
+
+/// Lists the videos of the mix YouTube generates around a video, starting with the video itself.
+pub(crate) async fn mix_entries(url: &str) -> Result<Vec<PlaylistEntry>, youtube_dl::Error> {
+    let id = match video_id(url) {
+        Some(id) => id,
+        None => return Ok(Vec::new()),
+    };
+
+    let mix = format!("https://www.youtube.com/watch?v={0}&list=RD{0}", id);
+    playlist_entries(&mix).await.map(|(_, entries)| entries)
+}
+
+/// Lists a playlist's videos without resolving their streams, in playlist order.
+pub(crate) async fn playlist_entries(url: &str) -> Result<(Option<String>, Vec<PlaylistEntry>), youtube_dl::Error> {
+    let output = YoutubeDl::new(url)
+        .flat_playlist(true)
+        .run_async()
+        .await?;
+
+    let playlist = match output {
+        YoutubeDlOutput::Playlist(playlist) => *playlist,
+        YoutubeDlOutput::SingleVideo(video) => {
+            return Ok((None, to_entry(*video).into_iter().collect()));
+        },
+    };
+
+    let entries = playlist.entries
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(to_entry)
+        .collect();
+
+    Ok((playlist.title, entries))
+}
+
+fn to_entry(video: SingleVideo) -> Option<PlaylistEntry> {
+    let url = video.webpage_url.clone()
+        .or(video.url.clone())
+        .unwrap_or_else(|| format!("https://www.youtube.com/watch?v={}", video.id));
+
+    // Deleted and private videos stay listed in flat playlists, but cannot be played.
+    if video.title.as_deref().is_none_or(|title| title == "[Deleted video]" || title == "[Private video]") {
+        return None;
+    }
+
+    let thumbnail = video.thumbnail.clone().or_else(|| video.thumbnails
+        .as_ref()
+        .and_then(|thumbnails| thumbnails.last())
+        .and_then(|thumbnail| thumbnail.url.clone()));
+
+    let metadata = AuxMetadata {
+        title: video.title,
+        channel: video.channel.or(video.uploader),
+        duration: video.duration
+            .and_then(|duration| duration.as_f64())
+            .map(Duration::from_secs_f64),
+        source_url: Some(url.clone()),
+        thumbnail,
+        ..Default::default()
+    };
+
+    Some(PlaylistEntry { url, metadata })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playlist_url_reads_playlist_and_shared_links() {
+        let expected = Some(String::from("https://www.youtube.com/playlist?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"));
+        assert_eq!(playlist_url("https://www.youtube.com/playlist?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"), expected);
+        assert_eq!(playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG&index=2"), expected);
+        assert_eq!(playlist_url("https://youtu.be/dQw4w9WgXcQ?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"), expected);
+        assert_eq!(playlist_url("https://music.youtube.com/playlist?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"), expected);
+    }
+
+    #[test]
+    fn playlist_url_skips_videos_and_mixes() {
+        assert_eq!(playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(playlist_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=RDdQw4w9WgXcQ"), None);
+        assert_eq!(playlist_url("https://www.youtube.com/playlist?list="), None);
+        assert_eq!(playlist_url("https://example.com/watch?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"), None);
+    }
+
+    #[test]
+    fn video_id_reads_every_link_format() {
+        assert_eq!(video_id("https://youtu.be/dQw4w9WgXcQ?t=90"), Some("dQw4w9WgXcQ"));
+        assert_eq!(video_id("https://www.youtube.com/watch?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG&v=dQw4w9WgXcQ"), Some("dQw4w9WgXcQ"));
+        assert_eq!(video_id("https://m.youtube.com/watch?v=dQw4w9WgXcQ#comments"), Some("dQw4w9WgXcQ"));
+        assert_eq!(video_id("https://music.youtube.com/watch?v=dQw4w9WgXcQ&feature=share"), Some("dQw4w9WgXcQ"));
+        assert_eq!(video_id("youtube.com/watch?v=dQw4w9WgXcQ"), Some("dQw4w9WgXcQ"));
+    }
+
+    #[test]
+    fn video_id_rejects_malformed_links() {
+        assert_eq!(video_id("https://youtu.be/"), None);
+        assert_eq!(video_id("https://youtu.be/?t=90"), None);
+        assert_eq!(video_id("https://www.youtube.com/watch"), None);
+        assert_eq!(video_id("https://www.youtube.com/watch?v="), None);
+        assert_eq!(video_id("https://www.youtube.com/watch?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"), None);
+        assert_eq!(video_id("https://example.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(video_id("never gonna give you up"), None);
+    }
+
+    #[test]
+    fn start_time_reads_every_format() {
+        assert_eq!(start_time("https://youtu.be/dQw4w9WgXcQ?t=90"), Some(Duration::from_secs(90)));
+        assert_eq!(start_time("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=90s"), Some(Duration::from_secs(90)));
+        assert_eq!(start_time("https://youtube.com/watch?v=dQw4w9WgXcQ&t=1h2m3s"), Some(Duration::from_secs(3723)));
+    }
+
+    #[test]
+    fn start_time_rejects_invalid_input() {
+        assert_eq!(start_time("https://youtu.be/dQw4w9WgXcQ"), None);
+        assert_eq!(start_time("https://youtu.be/dQw4w9WgXcQ?t=0"), None);
+        assert_eq!(start_time("https://youtu.be/dQw4w9WgXcQ?t=1x"), None);
+        assert_eq!(start_time("https://example.com/watch?t=90"), None);
+        assert_eq!(start_time("https://youtu.be/dQw4w9WgXcQ?t=99999999999999999h"), None);
+    }
+}